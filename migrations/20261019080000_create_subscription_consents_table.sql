@@ -0,0 +1,13 @@
+-- Add migration script here
+
+CREATE TABLE subscription_consents (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    source TEXT NOT NULL,
+    consent_text_version TEXT NOT NULL,
+    source_ip TEXT NULL,
+    user_agent TEXT NULL,
+    consented_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    confirmation_ip TEXT NULL,
+    PRIMARY KEY (subscriber_id)
+);
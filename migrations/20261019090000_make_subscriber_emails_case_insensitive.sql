@@ -0,0 +1,54 @@
+-- Subscriber emails used to be compared as typed, so `Alice@Example.com` and
+-- `alice@example.com` could both be subscribed and both received every issue.
+-- Addresses are still stored as typed, but they are now unique regardless of case.
+
+-- One-off merge of the subscribers that only differ by case.
+-- The survivor of each group is the one with the most advanced status,
+-- and the oldest subscription among those.
+CREATE TEMPORARY TABLE subscription_merges AS
+SELECT id AS duplicate_id, survivor_id
+FROM (
+    SELECT
+        id,
+        first_value(id) OVER (
+            PARTITION BY lower(email)
+            ORDER BY
+                CASE status WHEN 'confirmed' THEN 1 ELSE 0 END DESC,
+                subscribed_at ASC,
+                id ASC
+        ) AS survivor_id
+    FROM subscriptions
+) ranked
+WHERE id <> survivor_id;
+
+-- Confirmation links sent to a duplicate keep working for the survivor.
+UPDATE subscription_tokens t
+SET subscriber_id = m.survivor_id
+FROM subscription_merges m
+WHERE t.subscriber_id = m.duplicate_id;
+
+-- Every consent given for the mailbox is kept, so a subscriber may now have several.
+ALTER TABLE subscription_consents DROP CONSTRAINT subscription_consents_pkey;
+CREATE INDEX subscription_consents_subscriber_id_idx ON subscription_consents (subscriber_id);
+
+UPDATE subscription_consents c
+SET subscriber_id = m.survivor_id
+FROM subscription_merges m
+WHERE c.subscriber_id = m.duplicate_id;
+
+DELETE FROM subscriptions s
+USING subscription_merges m
+WHERE s.id = m.duplicate_id;
+
+DROP TABLE subscription_merges;
+
+-- Addresses are only lowercased to be compared: mail goes to the address as typed.
+CREATE UNIQUE INDEX subscriptions_email_canonical_key ON subscriptions (lower(email));
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+
+-- Pending deliveries must not reach the same mailbox twice either.
+DELETE FROM issue_delivery_queue q
+USING issue_delivery_queue other
+WHERE q.newsletter_issue_id = other.newsletter_issue_id
+    AND lower(q.subscriber_email) = lower(other.subscriber_email)
+    AND q.subscriber_email > other.subscriber_email;
@@ -0,0 +1,14 @@
+-- Admins can now have an email address, set when they join through an invitation.
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+CREATE TABLE user_invitations (
+    -- SHA-256 of the token mailed to the invitee, see `hash_token`.
+    token_hash TEXT NOT NULL,
+    email TEXT NOT NULL,
+    invited_by UUID NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    accepted_by UUID NULL REFERENCES users (user_id),
+    PRIMARY KEY (token_hash)
+);
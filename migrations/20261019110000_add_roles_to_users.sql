@@ -0,0 +1,11 @@
+-- Existing admins keep full control of the newsletter.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner';
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE users ADD CONSTRAINT users_role_is_known
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+
+-- The role an invitee is granted when they accept their invitation.
+ALTER TABLE user_invitations ADD COLUMN role TEXT NOT NULL DEFAULT 'editor';
+ALTER TABLE user_invitations ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE user_invitations ADD CONSTRAINT user_invitations_role_is_known
+    CHECK (role IN ('owner', 'editor', 'viewer'));
@@ -0,0 +1,12 @@
+CREATE TABLE password_reset_tokens (
+    -- SHA-256 of the token that was mailed: a leaked table cannot be used to reset passwords.
+    token_hash TEXT NOT NULL,
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (token_hash)
+);
+
+-- Sessions that started before this instant are no longer valid.
+ALTER TABLE users ADD COLUMN sessions_revoked_at timestamptz NULL;
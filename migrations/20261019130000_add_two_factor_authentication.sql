@@ -0,0 +1,13 @@
+-- Base32-encoded RFC 6238 secret, set once the user has confirmed their authenticator app.
+-- It is stored as is: checking a code needs the secret itself, so it cannot be hashed.
+-- Whoever can read this column only gets the second factor; the password hash still stands.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- The last time step a code was accepted for, so that a code cannot be replayed.
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes (
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
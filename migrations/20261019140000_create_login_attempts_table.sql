@@ -0,0 +1,12 @@
+CREATE TABLE login_attempts (
+    id BIGSERIAL PRIMARY KEY,
+    -- Attempts are recorded for unknown usernames too, so lockouts do not reveal which exist.
+    username TEXT NOT NULL,
+    user_id UUID NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    ip TEXT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('success', 'failure', 'locked_out')),
+    attempted_at timestamptz NOT NULL
+);
+
+CREATE INDEX login_attempts_username_attempted_at ON login_attempts (username, attempted_at DESC);
+CREATE INDEX login_attempts_attempted_at ON login_attempts (attempted_at DESC);
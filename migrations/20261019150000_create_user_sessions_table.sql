@@ -0,0 +1,12 @@
+-- One row per login, so that users can see and revoke their sessions from any device.
+CREATE TABLE user_sessions (
+    session_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    user_agent TEXT NULL,
+    ip TEXT NULL,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    revoked_at timestamptz NULL
+);
+
+CREATE INDEX user_sessions_user_id ON user_sessions (user_id);
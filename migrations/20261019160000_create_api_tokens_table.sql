@@ -0,0 +1,14 @@
+-- Personal tokens that let scripts act on behalf of an admin user.
+CREATE TABLE api_tokens (
+    token_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- Only a SHA-256 hash is kept: the token itself is shown once, when it is created.
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
+
+CREATE INDEX api_tokens_user_id ON api_tokens (user_id);
@@ -0,0 +1,2 @@
+-- Lets the cleanup job find expired idempotency records without scanning the table.
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
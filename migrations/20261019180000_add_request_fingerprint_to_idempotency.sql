@@ -0,0 +1,2 @@
+-- Records created before fingerprints were stored keep a NULL and match any request.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT;
@@ -0,0 +1,9 @@
+-- Anonymous requests, such as subscriptions, have no user to scope their keys to.
+-- They are scoped to a hash of the client's address instead, written by the application:
+-- a key seen by someone else must not replay a response that was saved for another client.
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE idempotency ADD COLUMN scope uuid NULL;
+UPDATE idempotency SET scope = user_id;
+ALTER TABLE idempotency ALTER COLUMN scope SET NOT NULL;
+ALTER TABLE idempotency ADD PRIMARY KEY (scope, idempotency_key);
@@ -0,0 +1,3 @@
+-- Like subscribers, admins are told apart regardless of the case of their address:
+-- an invitation must not create a second account for the same mailbox.
+CREATE UNIQUE INDEX users_email_canonical_key ON users (lower(email));
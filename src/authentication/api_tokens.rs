@@ -0,0 +1,388 @@
+//! src/authentication/api_tokens.rs
+
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{Role, UserId};
+use crate::error::Problem;
+use crate::routes::error_chain_fmt;
+use crate::utils::{generate_token, hash_token};
+
+/// Tokens start with a recognisable prefix so that secret scanners can spot leaked ones.
+const API_TOKEN_PREFIX: &str = "nlt_";
+const API_TOKEN_LENGTH: usize = 40;
+
+/// What an API token may be used for, on top of what the role of its owner allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "newsletters:publish")]
+    PublishNewsletters,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 1] = [ApiScope::PublishNewsletters];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "newsletters:publish",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "Publish newsletter issues",
+        }
+    }
+
+    /// The role needed to grant the scope to a token: tokens cannot do more than their owner.
+    pub fn required_role(&self) -> Role {
+        match self {
+            ApiScope::PublishNewsletters => Role::Editor,
+        }
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "newsletters:publish" => Ok(ApiScope::PublishNewsletters),
+            other => Err(format!("{} is not a known scope.", other)),
+        }
+    }
+}
+
+impl Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct ApiTokenSummary {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Store a new token for `user_id`, returning it in the clear: it cannot be recovered later.
+#[tracing::instrument(name = "Create an API token", skip(pool))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    pool: &PgPool,
+) -> Result<String, anyhow::Error> {
+    let token = format!("{API_TOKEN_PREFIX}{}", generate_token(API_TOKEN_LENGTH));
+    let scopes: Vec<String> = scopes
+        .iter()
+        .map(|scope| scope.as_str().to_owned())
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API token.")?;
+
+    Ok(token)
+}
+
+#[tracing::instrument(name = "List the API tokens of a user", skip(pool))]
+pub async fn get_api_tokens(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<ApiTokenSummary>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiTokenSummary,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens of a user.")?;
+
+    Ok(tokens)
+}
+
+/// Revoke one token of a user, returning `false` if there was no such active token.
+#[tracing::instrument(name = "Revoke an API token", skip(pool))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API token.")?;
+
+    Ok(revoked.rows_affected() == 1)
+}
+
+/// The user behind a valid `Authorization: Bearer` API token.
+///
+/// Handlers get the same `UserId` as behind `reject_anonymous_users`, but must check
+/// the scope they need with `require` before acting on it.
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    pub user_id: UserId,
+    pub role: Role,
+    scopes: Vec<ApiScope>,
+}
+
+impl ApiUser {
+    /// Succeed if the token was granted `scope` and its owner still has the `role` it needs.
+    pub fn require(&self, scope: ApiScope, role: Role) -> Result<(), ApiAuthError> {
+        if self.scopes.contains(&scope) && self.role.allows(role) {
+            Ok(())
+        } else {
+            Err(ApiAuthError::InsufficientScope(scope))
+        }
+    }
+}
+
+/// Reject requests without a valid API token with a `401 Unauthorized`.
+///
+/// Wrapped around the idempotency middleware, so that anonymous clients can neither
+/// claim idempotency keys nor be told about missing ones before being turned away.
+pub async fn reject_anonymous_api_clients(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    match req.extract::<ApiUser>().await {
+        Ok(user) => {
+            req.extensions_mut().insert(user);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(error) => Ok(req.error_response(error).map_into_right_body()),
+    }
+}
+
+impl FromRequest for ApiUser {
+    type Error = ApiAuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Middleware that authenticated the token already leaves the user behind.
+        if let Some(user) = req.extensions().get::<ApiUser>().cloned() {
+            return Box::pin(async move { Ok(user) });
+        }
+
+        let token = bearer_token(req);
+        let pool = req
+            .app_data::<Data<PgPool>>()
+            .expect("The database pool must be registered as application data")
+            .clone();
+
+        Box::pin(async move {
+            let token = token.ok_or(ApiAuthError::MissingToken)?;
+            authenticate_api_token(&token, &pool)
+                .await?
+                .ok_or(ApiAuthError::InvalidToken)
+        })
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_owned())
+}
+
+#[tracing::instrument(name = "Authenticate an API token", skip(token, pool))]
+async fn authenticate_api_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<ApiUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        FROM users
+        WHERE api_tokens.token_hash = $1
+            AND api_tokens.revoked_at IS NULL
+            AND users.user_id = api_tokens.user_id
+        RETURNING api_tokens.user_id, api_tokens.scopes, users.role
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up an API token.")?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let role = Role::try_from(row.role).map_err(anyhow::Error::msg)?;
+    // Scopes that are no longer known grant nothing.
+    let scopes = row
+        .scopes
+        .into_iter()
+        .filter_map(|scope| ApiScope::try_from(scope).ok())
+        .collect();
+
+    Ok(Some(ApiUser {
+        user_id: UserId::from(row.user_id),
+        role,
+        scopes,
+    }))
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiAuthError {
+    #[error("An API token must be provided in an `Authorization: Bearer` header.")]
+    MissingToken,
+    #[error("The API token is invalid or has been revoked.")]
+    InvalidToken,
+    #[error("The API token does not allow you to {}.", .0.description().to_lowercase())]
+    InsufficientScope(ApiScope),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiAuthError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for ApiAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingToken | Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::InsufficientScope(_) => StatusCode::FORBIDDEN,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        let mut response = match self {
+            Self::UnexpectedError(_) => problem,
+            _ => problem.with_detail(self.to_string()),
+        }
+        .into_response();
+
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::{bearer_token, ApiScope, ApiUser};
+    use crate::authentication::{Role, UserId};
+    use crate::utils::hash_token;
+    use uuid::Uuid;
+
+    fn api_user(role: Role, scopes: Vec<ApiScope>) -> ApiUser {
+        ApiUser {
+            user_id: UserId::from(Uuid::new_v4()),
+            role,
+            scopes,
+        }
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::try_from(scope.as_str().to_string()), Ok(scope));
+        }
+        assert!(ApiScope::try_from("subscribers:delete".to_string()).is_err());
+    }
+
+    #[test]
+    fn tokens_need_both_the_scope_and_the_role() {
+        let scope = ApiScope::PublishNewsletters;
+
+        assert!(api_user(Role::Editor, vec![scope])
+            .require(scope, Role::Editor)
+            .is_ok());
+        assert!(api_user(Role::Editor, vec![])
+            .require(scope, Role::Editor)
+            .is_err());
+        assert!(api_user(Role::Viewer, vec![scope])
+            .require(scope, Role::Editor)
+            .is_err());
+    }
+
+    #[test]
+    fn bearer_tokens_are_read_from_the_authorization_header() {
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "Bearer nlt_abc"))
+            .to_http_request();
+        assert_eq!(bearer_token(&request).as_deref(), Some("nlt_abc"));
+
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "bearer nlt_abc"))
+            .to_http_request();
+        assert_eq!(bearer_token(&request).as_deref(), Some("nlt_abc"));
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_ignored() {
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "Basic dXNlcjpwYXNz"))
+            .to_http_request();
+        assert_eq!(bearer_token(&request), None);
+        assert_eq!(
+            bearer_token(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    #[test]
+    fn tokens_are_hashed_deterministically() {
+        assert_eq!(hash_token("nlt_abc"), hash_token("nlt_abc"));
+        assert_ne!(hash_token("nlt_abc"), hash_token("nlt_abd"));
+    }
+}
@@ -0,0 +1,148 @@
+//! src/authentication/csrf.rs
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::REFERER;
+use actix_web::http::{Method, StatusCode, Uri};
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage};
+use actix_web_flash_messages::FlashMessage;
+
+use crate::error::Problem;
+use crate::session_state::TypedSession;
+use crate::utils::{constant_time_eq, error_500, generate_token, read_body_field, see_other};
+
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+/// The anti-forgery token of the current session, to embed in every form that posts.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub(crate) String);
+
+impl CsrfToken {
+    /// A hidden input carrying the token; tokens are alphanumeric, so nothing needs escaping.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{CSRF_FORM_FIELD}" value="{}">"#,
+            self.0
+        )
+    }
+}
+
+/// Reject state-changing requests that do not echo the session's CSRF token.
+///
+/// Other sites can make a browser submit a form along with our session cookie, but they
+/// cannot read our pages to learn the token. Safe requests get the token in their
+/// extensions so that handlers can render it with `CsrfToken::form_field`.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    let token = match session.get_csrf_token().map_err(error_500)? {
+        Some(token) => token,
+        None => {
+            let token = generate_token(CSRF_TOKEN_LENGTH);
+            session.insert_csrf_token(&token).map_err(error_500)?;
+            token
+        }
+    };
+
+    if !is_safe(req.method()) {
+        let submitted = match req
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(value) => Some(value.to_owned()),
+            None => read_body_field(&mut req, CSRF_FORM_FIELD).await?,
+        };
+
+        let is_valid = submitted
+            .map(|submitted| constant_time_eq(submitted.as_bytes(), token.as_bytes()))
+            .unwrap_or(false);
+        if !is_valid {
+            FlashMessage::error("Your form has expired. Please try again.").send();
+            let response = Problem::new(StatusCode::FORBIDDEN)
+                .with_detail("The request did not carry a valid CSRF token.")
+                .attach_to(see_other(&retry_location(&req)));
+            let error = anyhow::anyhow!("The CSRF token is missing or does not match");
+            let error = InternalError::from_response(error, response);
+            return Ok(req.error_response(error).map_into_right_body());
+        }
+    }
+
+    req.extensions_mut().insert(CsrfToken(token));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Send users back to the admin page they submitted the form from.
+///
+/// Only the path of the `Referer` is kept, so the redirect never leaves this site.
+fn retry_location(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REFERER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Uri>().ok())
+        .map(|uri| uri.path().to_owned())
+        .filter(|path| path.starts_with("/admin/"))
+        .unwrap_or_else(|| "/admin/dashboard".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::Method;
+    use actix_web::test::TestRequest;
+
+    use super::{is_safe, retry_location, CsrfToken};
+
+    #[test]
+    fn only_safe_methods_skip_the_check() {
+        assert!(is_safe(&Method::GET));
+        assert!(is_safe(&Method::HEAD));
+        assert!(!is_safe(&Method::POST));
+        assert!(!is_safe(&Method::DELETE));
+    }
+
+    #[test]
+    fn forged_requests_are_sent_back_to_the_admin_page_they_came_from() {
+        let request = TestRequest::post()
+            .insert_header(("Referer", "http://localhost:8000/admin/password"))
+            .to_srv_request();
+        assert_eq!(retry_location(&request), "/admin/password");
+    }
+
+    #[test]
+    fn referers_outside_the_admin_area_are_ignored() {
+        let request = TestRequest::post()
+            .insert_header(("Referer", "https://evil.example/phishing"))
+            .to_srv_request();
+        assert_eq!(retry_location(&request), "/admin/dashboard");
+
+        assert_eq!(
+            retry_location(&TestRequest::post().to_srv_request()),
+            "/admin/dashboard"
+        );
+    }
+
+    #[test]
+    fn the_form_field_carries_the_token() {
+        let token = CsrfToken("abc123".into());
+        assert_eq!(
+            token.form_field(),
+            r#"<input type="hidden" name="csrf_token" value="abc123">"#
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! src/authentication/login_attempts.rs
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::LoginLockoutSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    /// The username was locked: the password was not even considered.
+    LockedOut,
+}
+
+impl LoginOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::Failure => "failure",
+            LoginOutcome::LockedOut => "locked_out",
+        }
+    }
+}
+
+pub struct FailedLoginAttempt {
+    pub username: String,
+    pub ip: Option<String>,
+    pub outcome: String,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// Locks a username once it has failed to log in too many times in a row.
+///
+/// Locks are keyed on the username as typed rather than on accounts, so unknown
+/// usernames lock exactly like real ones and a lockout reveals nothing.
+#[derive(Clone, Copy)]
+pub struct LoginLockout {
+    max_consecutive_failures: u32,
+    lockout: TimeDelta,
+}
+
+impl LoginLockout {
+    pub fn new(settings: LoginLockoutSettings) -> Self {
+        Self {
+            max_consecutive_failures: settings.max_consecutive_failures,
+            lockout: TimeDelta::from_std(settings.lockout())
+                .unwrap_or_else(|_| TimeDelta::max_value()),
+        }
+    }
+
+    #[tracing::instrument(name = "Check whether a username is locked out", skip(self, pool))]
+    pub async fn is_locked_out(
+        &self,
+        username: &str,
+        pool: &PgPool,
+    ) -> Result<bool, anyhow::Error> {
+        if self.max_consecutive_failures == 0 {
+            return Ok(false);
+        }
+
+        // Attempts rejected because of the lock do not extend it.
+        let recent = sqlx::query!(
+            r#"
+            SELECT outcome, attempted_at
+            FROM login_attempts
+            WHERE username = $1 AND outcome IN ('success', 'failure')
+            ORDER BY attempted_at DESC
+            LIMIT $2
+            "#,
+            username,
+            i64::from(self.max_consecutive_failures)
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve recent login attempts.")?;
+
+        let consecutive_failures = recent
+            .iter()
+            .take_while(|attempt| attempt.outcome == LoginOutcome::Failure.as_str())
+            .count();
+
+        Ok(
+            consecutive_failures == self.max_consecutive_failures as usize
+                && recent[0].attempted_at + self.lockout > Utc::now(),
+        )
+    }
+}
+
+#[tracing::instrument(name = "Record a login attempt", skip(pool))]
+pub async fn record_login_attempt(
+    username: &str,
+    user_id: Option<Uuid>,
+    ip: Option<&str>,
+    outcome: LoginOutcome,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO login_attempts (username, user_id, ip, outcome, attempted_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        username,
+        user_id,
+        ip,
+        outcome.as_str()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a login attempt.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get recent failed login attempts", skip(pool))]
+pub async fn get_recent_failed_login_attempts(
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<FailedLoginAttempt>, anyhow::Error> {
+    let attempts = sqlx::query_as!(
+        FailedLoginAttempt,
+        r#"
+        SELECT username, ip, outcome, attempted_at
+        FROM login_attempts
+        WHERE outcome <> 'success'
+        ORDER BY attempted_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve recent failed login attempts.")?;
+
+    Ok(attempts)
+}
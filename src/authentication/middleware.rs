@@ -3,13 +3,21 @@
 use std::fmt::Display;
 use std::ops::Deref;
 
-use actix_web::body::MessageBody;
+use actix_web::body::{EitherBody, MessageBody};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
 use actix_web::middleware::Next;
+use actix_web::web::Data;
 use actix_web::{FromRequest, HttpMessage};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::sessions::{session_is_current, touch_session};
+use super::Role;
+use crate::error::Problem;
 use crate::session_state::TypedSession;
 use crate::utils::{error_500, see_other};
 
@@ -22,6 +30,12 @@ impl Display for UserId {
     }
 }
 
+impl From<Uuid> for UserId {
+    fn from(user_id: Uuid) -> Self {
+        Self(user_id)
+    }
+}
+
 impl Deref for UserId {
     type Target = Uuid;
 
@@ -33,21 +47,103 @@ impl Deref for UserId {
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
-) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(error_500)? {
-        Some(user_id) => {
+    let session_id = session.get_session_id().map_err(error_500)?;
+    let user = match (session.get_user_id().map_err(error_500)?, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<Data<PgPool>>()
+                .expect("The database pool must be registered as application data");
+            let logged_in_at = session.get_logged_in_at().map_err(error_500)?;
+            let access = get_access(user_id, pool)
+                .await
+                .map_err(error_500)?
+                .filter(|access| session_is_current(logged_in_at, access.sessions_revoked_at));
+            match access {
+                Some(access) => touch_session(session_id, user_id, pool)
+                    .await
+                    .map_err(error_500)?
+                    .then_some((user_id, access.role)),
+                None => None,
+            }
+        }
+        // Sessions that predate session tracking cannot be listed nor revoked: log them out.
+        _ => None,
+    };
+
+    match user {
+        Some((user_id, role)) => {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            req.extensions_mut().insert(role);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
         }
         None => {
-            let response = see_other("/login");
+            // The account may have been removed, or the session revoked, since logging in.
+            session.log_out();
+            let response = Problem::new(StatusCode::UNAUTHORIZED)
+                .with_detail("You must be logged in to access this page.")
+                .attach_to(see_other("/login"));
             let error = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(error, response).into())
+            let error = InternalError::from_response(error, response);
+            Ok(req.error_response(error).map_into_right_body())
+        }
+    }
+}
+
+/// Reject users whose role does not grant at least `required` with a `403 Forbidden`.
+///
+/// Must run inside `reject_anonymous_users`, which loads the role of the current user.
+pub async fn require_role(
+    required: Role,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let role = req.extensions().get::<Role>().copied();
+
+    match role {
+        Some(role) if role.allows(required) => next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+        _ => {
+            let response = Problem::new(StatusCode::FORBIDDEN)
+                .with_detail("You do not have permission to perform this action.")
+                .into_response();
+            let error = anyhow::anyhow!("The user does not have the {} role", required);
+            let error = InternalError::from_response(error, response);
+            Ok(req.error_response(error).map_into_right_body())
         }
     }
 }
+
+struct UserAccess {
+    role: Role,
+    sessions_revoked_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get the role of a user", skip(pool))]
+async fn get_access(user_id: Uuid, pool: &PgPool) -> Result<Option<UserAccess>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT role, sessions_revoked_at FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the role of a user.")?;
+
+    row.map(|row| {
+        let role = Role::try_from(row.role).map_err(anyhow::Error::msg)?;
+        Ok(UserAccess {
+            role,
+            sessions_revoked_at: row.sessions_revoked_at,
+        })
+    })
+    .transpose()
+}
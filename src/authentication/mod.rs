@@ -1,8 +1,34 @@
 //! src/authentication/mod.rs
 
+mod api_tokens;
+mod csrf;
+mod login_attempts;
 mod middleware;
 mod password;
+mod role;
+mod sessions;
+mod two_factor;
 
-pub use middleware::reject_anonymous_users;
+pub use api_tokens::{
+    create_api_token, get_api_tokens, reject_anonymous_api_clients, revoke_api_token, ApiAuthError,
+    ApiScope, ApiTokenSummary, ApiUser,
+};
+pub use csrf::{reject_forged_requests, CsrfToken, CSRF_FORM_FIELD, CSRF_HEADER};
+pub use login_attempts::{
+    get_recent_failed_login_attempts, record_login_attempt, FailedLoginAttempt, LoginLockout,
+    LoginOutcome,
+};
 pub use middleware::UserId;
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use middleware::{reject_anonymous_users, require_role};
+pub use password::{
+    change_password, create_user, validate_credentials, AuthError, Credentials, PasswordHashing,
+};
+pub use role::Role;
+pub use sessions::{
+    get_active_sessions, revoke_other_sessions, revoke_session, revoke_sessions, start_session,
+    ActiveSession, SessionClient,
+};
+pub use two_factor::{
+    disable_two_factor, enable_two_factor, generate_recovery_codes, get_totp_secret,
+    verify_second_factor, TotpSecret,
+};
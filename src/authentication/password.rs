@@ -1,11 +1,14 @@
 use anyhow::Context;
 use redact::Secret;
-use sqlx::PgPool;
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use tracing::Instrument;
 use uuid::Uuid;
 
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 
+use super::Role;
+use crate::configuration::PasswordHashingSettings;
 use crate::telemetry::Telemetry;
 
 #[derive(thiserror::Error, Debug)]
@@ -42,18 +45,74 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// The Argon2 parameters new password hashes are computed with.
+///
+/// Hashes record the parameters they were computed with, so raising these only
+/// affects new hashes; older ones are upgraded the next time their owner logs in.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    /// Verified against when the username is unknown, so that unknown usernames
+    /// take as long to reject as known ones.
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(
+            settings.memory_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("Invalid Argon2 parameters")?;
+
+        let mut hashing = Self {
+            params,
+            dummy_hash: Secret::new(String::new()),
+        };
+        hashing.dummy_hash = hashing.hash(Secret::new(Uuid::new_v4().to_string()))?;
+
+        Ok(hashing)
+    }
+
+    fn hasher(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    fn hash(&self, password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let password_hash = self
+            .hasher()
+            .hash_password(password.expose_secret().as_bytes(), &salt)?
+            .to_string();
+        Ok(Secret::new(password_hash))
+    }
+
+    /// Whether `hash` is weaker than the hashes computed with the current parameters.
+    fn is_outdated(&self, hash: &PasswordHash) -> bool {
+        let hash_params = match Params::try_from(hash) {
+            Ok(params) => params,
+            Err(_) => return true,
+        };
+
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || hash_params.m_cost() < self.params.m_cost()
+            || hash_params.t_cost() < self.params.t_cost()
+            || hash_params.p_cost() < self.params.p_cost()
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<Uuid, AuthError> {
     let mut user_id: Option<Uuid> = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.dummy_hash.clone();
 
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool).await?
@@ -62,47 +121,116 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
-    Telemetry::spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
-    })
-    .await
-    .context("Failed to spawn blocking task.")??;
+    let password = credentials.password;
+    let verifier = hashing.clone();
+    let (outcome, expected_password_hash, password) =
+        Telemetry::spawn_blocking_with_tracing(move || {
+            let outcome = verify_password_hash(&expected_password_hash, &password, &verifier);
+            (outcome, expected_password_hash, password)
+        })
+        .await
+        .context("Failed to spawn blocking task.")?;
+    let is_outdated = outcome?;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if is_outdated {
+        // The user is waiting on their login: upgrade the hash once they are in.
+        let hashing = hashing.clone();
+        let pool = pool.clone();
+        tokio::spawn(
+            async move {
+                if let Err(error) =
+                    rehash_password(user_id, password, expected_password_hash, &hashing, &pool)
+                        .await
+                {
+                    tracing::warn!(
+                        error.cause_chain = ?error,
+                        error.message = %error,
+                        "Failed to upgrade an outdated password hash"
+                    );
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+    }
+
+    Ok(user_id)
 }
 
+/// Check a password against its hash, returning whether the hash should be recomputed
+/// with the current parameters.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(expected_password_hash, password_candidate, hashing)
 )]
 fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+    expected_password_hash: &Secret<String>,
+    password_candidate: &Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<bool, AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
 
-    Argon2::default()
+    // Verification uses the parameters recorded in the hash, not the current ones.
+    hashing
+        .hasher()
         .verify_password(
             password_candidate.expose_secret().as_bytes(),
             &expected_password_hash,
         )
         .context("Invalid password.")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    Ok(hashing.is_outdated(&expected_password_hash))
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
-pub async fn change_password(
+/// Replace an outdated hash, unless the password was changed in the meantime.
+#[tracing::instrument(name = "Rehash password", skip(password, outdated_hash, hashing, pool))]
+async fn rehash_password(
     user_id: Uuid,
     password: Secret<String>,
+    outdated_hash: Secret<String>,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash =
-        Telemetry::spawn_blocking_with_tracing(move || compute_password_hash(password))
-            .await?
-            .context("Failed to hash password")?;
+    let hashing = hashing.clone();
+    let password_hash = Telemetry::spawn_blocking_with_tracing(move || hashing.hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        outdated_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an upgraded password hash in the database.")?;
+
+    Ok(())
+}
+
+/// Store a new password for the user, through `executor` so that callers can make it
+/// part of a larger transaction.
+#[tracing::instrument(name = "Change password", skip(password, hashing, executor))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash = Telemetry::spawn_blocking_with_tracing(move || hashing.hash(password))
+        .await?
+        .context("Failed to hash password")?;
 
     sqlx::query!(
         r#"
@@ -113,21 +241,44 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
 
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
-    Ok(Secret::new(password_hash))
+/// Store a new admin user, hashing their password the same way `change_password` does.
+#[tracing::instrument(name = "Create user", skip(transaction, password, hashing))]
+pub async fn create_user(
+    transaction: &mut Transaction<'static, Postgres>,
+    username: &str,
+    email: &str,
+    role: Role,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<Uuid, anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash = Telemetry::spawn_blocking_with_tracing(move || hashing.hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let user_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        email,
+        role.as_str()
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store a new user in the database.")?;
+
+    Ok(user_id)
 }
@@ -0,0 +1,79 @@
+//! src/authentication/role.rs
+
+use std::fmt::Display;
+
+/// What an admin user is allowed to do.
+///
+/// Roles are ordered by privilege: every role can do everything the roles below it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can read reports and subscriber lists.
+    Viewer,
+    /// Can also publish newsletters.
+    Editor,
+    /// Can also manage users.
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    /// Whether this role grants at least the privileges of `required`.
+    pub fn allows(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            other => Err(format!("{} is not a known role.", other)),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+
+    #[test]
+    fn roles_round_trip_through_their_string_form() {
+        for role in Role::ALL {
+            assert_eq!(Role::try_from(role.as_str().to_string()), Ok(role));
+        }
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert!(Role::try_from("admin".to_string()).is_err());
+    }
+
+    #[test]
+    fn higher_roles_include_the_privileges_of_lower_ones() {
+        assert!(Role::Owner.allows(Role::Editor));
+        assert!(Role::Editor.allows(Role::Viewer));
+        assert!(Role::Editor.allows(Role::Editor));
+        assert!(!Role::Viewer.allows(Role::Editor));
+        assert!(!Role::Editor.allows(Role::Owner));
+    }
+}
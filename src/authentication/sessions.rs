@@ -0,0 +1,233 @@
+//! src/authentication/sessions.rs
+
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+
+/// Where a login came from, as shown on the sessions page.
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionClient {
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+            ip: client_ip(request).map(|ip| ip.to_string()),
+        }
+    }
+}
+
+pub struct ActiveSession {
+    pub session_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// Register a new login, returning the id its session is tracked under.
+#[tracing::instrument(name = "Start a session", skip(client, pool))]
+pub async fn start_session(
+    user_id: Uuid,
+    client: &SessionClient,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, user_agent, ip, created_at, last_seen_at)
+        VALUES ($1, $2, $3, $4, now(), now())
+        "#,
+        session_id,
+        user_id,
+        client.user_agent,
+        client.ip
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new session.")?;
+
+    Ok(session_id)
+}
+
+/// Record activity on a session, returning `false` if it has been revoked.
+#[tracing::instrument(name = "Touch a session", skip(pool))]
+pub async fn touch_session(
+    session_id: Uuid,
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let touched = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record activity on a session.")?;
+
+    Ok(touched.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "List the active sessions of a user", skip(pool))]
+pub async fn get_active_sessions(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let sessions = sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT session_id, user_agent, ip, created_at, last_seen_at
+        FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the active sessions of a user.")?;
+
+    Ok(sessions)
+}
+
+/// Revoke one session of a user, returning `false` if there was no such active session.
+#[tracing::instrument(name = "Revoke a session", skip(pool))]
+pub async fn revoke_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke a session.")?;
+
+    Ok(revoked.rows_affected() == 1)
+}
+
+/// Log the user out everywhere but in `current_session_id`.
+#[tracing::instrument(name = "Revoke the other sessions of a user", skip(executor))]
+pub async fn revoke_other_sessions(
+    user_id: Uuid,
+    current_session_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<u64, anyhow::Error> {
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current_session_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the other sessions of a user.")?;
+
+    Ok(revoked.rows_affected())
+}
+
+/// Log the user out everywhere: sessions that started before now stop being accepted.
+#[tracing::instrument(name = "Revoke all sessions of a user", skip(pool))]
+pub async fn revoke_sessions(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE users
+            SET sessions_revoked_at = now()
+            WHERE user_id = $1
+            "#,
+            user_id
+        ))
+        .await
+        .context("Failed to revoke the sessions of a user.")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE user_sessions
+            SET revoked_at = now()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+            user_id
+        ))
+        .await
+        .context("Failed to revoke the sessions of a user.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke the sessions of a user.")
+}
+
+/// Whether a session that started at `logged_in_at` survived the last revocation.
+///
+/// Sessions without a start time predate revocation support and are only accepted
+/// while the user has never revoked their sessions.
+pub fn session_is_current(
+    logged_in_at: Option<DateTime<Utc>>,
+    sessions_revoked_at: Option<DateTime<Utc>>,
+) -> bool {
+    match (logged_in_at, sessions_revoked_at) {
+        (_, None) => true,
+        (Some(logged_in_at), Some(revoked_at)) => logged_in_at > revoked_at,
+        (None, Some(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::session_is_current;
+
+    #[test]
+    fn sessions_are_current_until_revoked() {
+        assert!(session_is_current(Some(Utc::now()), None));
+        assert!(session_is_current(None, None));
+    }
+
+    #[test]
+    fn sessions_started_before_a_revocation_are_not_current() {
+        let revoked_at = Utc::now();
+
+        assert!(!session_is_current(
+            Some(revoked_at - TimeDelta::seconds(1)),
+            Some(revoked_at)
+        ));
+        assert!(!session_is_current(None, Some(revoked_at)));
+        assert!(session_is_current(
+            Some(revoked_at + TimeDelta::seconds(1)),
+            Some(revoked_at)
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! src/authentication/two_factor.rs
+
+use anyhow::Context;
+use rand::{thread_rng, Rng};
+use redact::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+use crate::utils::{constant_time_eq, generate_token};
+
+const TOTP_ISSUER: &str = "Newsletter";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+/// Accept codes from one step before and after the current one to absorb clock drift.
+const TOTP_SKEW: u64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// A base32-encoded RFC 6238 secret shared with the user's authenticator app.
+///
+/// Unlike passwords and recovery codes, the secret is stored in plaintext: codes are
+/// computed from it, so the server needs it back.
+#[derive(Clone)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    /// A fresh 160-bit secret, the size RFC 4226 recommends for HMAC-SHA1.
+    pub fn generate() -> Self {
+        let bytes: [u8; 20] = thread_rng().gen();
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn parse(encoded: String) -> Result<Self, anyhow::Error> {
+        totp_rs::Secret::Encoded(encoded.clone())
+            .to_bytes()
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
+            .context("The TOTP secret is not valid base32.")?;
+        Ok(Self(Secret::new(encoded)))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match totp_rs::Secret::Raw(bytes.to_vec()).to_encoded() {
+            totp_rs::Secret::Encoded(encoded) => Self(Secret::new(encoded)),
+            totp_rs::Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+        }
+    }
+
+    pub fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+
+    /// The `otpauth://` URI authenticator apps use to enroll the secret, usually via a QR code.
+    pub fn otpauth_uri(&self, account_name: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECONDS}",
+            issuer = urlencoding::encode(TOTP_ISSUER),
+            account = urlencoding::encode(account_name),
+            secret = self.expose_secret(),
+        )
+    }
+
+    /// The time step `code` is valid for at `unix_time`, if any.
+    pub fn verify(&self, code: &str, unix_time: u64) -> Option<u64> {
+        let totp = self.totp();
+        let code = code.trim();
+        let current_step = unix_time / TOTP_STEP_SECONDS;
+
+        (current_step.saturating_sub(TOTP_SKEW)..=current_step + TOTP_SKEW).find(|step| {
+            let expected = totp.generate(step * TOTP_STEP_SECONDS);
+            // Compare in constant time so that response times do not leak digits.
+            constant_time_eq(expected.as_bytes(), code.as_bytes())
+        })
+    }
+
+    /// The code an authenticator app displays at `unix_time`.
+    pub fn generate_code(&self, unix_time: u64) -> String {
+        self.totp().generate(unix_time)
+    }
+
+    fn totp(&self) -> TOTP {
+        let bytes = totp_rs::Secret::Encoded(self.expose_secret().to_owned())
+            .to_bytes()
+            .expect("The TOTP secret was validated when it was created");
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            TOTP_DIGITS,
+            TOTP_SKEW as u8,
+            TOTP_STEP_SECONDS,
+            bytes,
+        )
+    }
+}
+
+/// Single-use codes that let a user log in without their authenticator app.
+pub fn generate_recovery_codes() -> Vec<String> {
+    std::iter::repeat_with(|| {
+        let code = generate_token(10).to_lowercase();
+        format!("{}-{}", &code[..5], &code[5..])
+    })
+    .take(RECOVERY_CODE_COUNT)
+    .collect()
+}
+
+/// Recovery codes are stored hashed; dashes, spaces and case are ignored when they are typed.
+fn hash_recovery_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalised.as_bytes()))
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[tracing::instrument(name = "Get the TOTP secret of a user", skip(pool))]
+pub async fn get_totp_secret(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<TotpSecret>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret of a user.")?;
+
+    row.totp_secret.map(TotpSecret::parse).transpose()
+}
+
+/// Turn two-factor authentication on, replacing any previous recovery codes.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(secret, recovery_codes, pool)
+)]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &TotpSecret,
+    recovery_codes: &[String],
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_secret = $1, totp_last_used_step = NULL
+            WHERE user_id = $2
+            "#,
+            secret.expose_secret(),
+            user_id
+        ))
+        .await
+        .context("Failed to store the TOTP secret of a user.")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            user_id
+        ))
+        .await
+        .context("Failed to delete old recovery codes.")?;
+    for code in recovery_codes {
+        transaction
+            .execute(sqlx::query!(
+                "INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+                user_id,
+                hash_recovery_code(code)
+            ))
+            .await
+            .context("Failed to store a recovery code.")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_secret = NULL, totp_last_used_step = NULL
+            WHERE user_id = $1
+            "#,
+            user_id
+        ))
+        .await
+        .context("Failed to remove the TOTP secret of a user.")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            user_id
+        ))
+        .await
+        .context("Failed to delete recovery codes.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")
+}
+
+/// Check the second factor of a login: a current TOTP code or an unused recovery code.
+///
+/// Both are consumed on success, so neither a TOTP code nor a recovery code can be replayed.
+#[tracing::instrument(name = "Verify a second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let Some(secret) = get_totp_secret(user_id, pool).await? else {
+        return Ok(false);
+    };
+
+    if let Some(step) = secret.verify(code, unix_now()) {
+        let accepted = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2
+                AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step as i64,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the use of a TOTP code.")?;
+
+        return Ok(accepted.rows_affected() == 1);
+    }
+
+    let used = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the use of a recovery code.")?;
+
+    Ok(used.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_codes, hash_recovery_code, TotpSecret};
+
+    // The SHA1 secret from the test vectors in RFC 6238, appendix B.
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret::from_bytes(b"12345678901234567890")
+    }
+
+    #[test]
+    fn codes_match_the_rfc_6238_test_vectors() {
+        let secret = rfc_secret();
+
+        assert_eq!(secret.generate_code(59), "287082");
+        assert_eq!(secret.generate_code(1111111109), "081804");
+        assert_eq!(secret.generate_code(1234567890), "005924");
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let secret = rfc_secret();
+        let now = 1234567890;
+        let step = now / 30;
+
+        assert_eq!(secret.verify(&secret.generate_code(now), now), Some(step));
+        assert_eq!(
+            secret.verify(&secret.generate_code(now - 30), now),
+            Some(step - 1)
+        );
+        assert_eq!(
+            secret.verify(&secret.generate_code(now + 30), now),
+            Some(step + 1)
+        );
+        assert_eq!(secret.verify(&secret.generate_code(now - 90), now), None);
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        let secret = rfc_secret();
+
+        assert_eq!(secret.verify("", 59), None);
+        assert_eq!(secret.verify("28708", 59), None);
+        assert_eq!(secret.verify("2870822", 59), None);
+    }
+
+    #[test]
+    fn generated_secrets_round_trip_through_their_encoding() {
+        let secret = TotpSecret::generate();
+        let parsed = TotpSecret::parse(secret.expose_secret().to_owned()).unwrap();
+
+        assert_eq!(parsed.generate_code(59), secret.generate_code(59));
+        assert!(TotpSecret::parse("not base32!".into()).is_err());
+    }
+
+    #[test]
+    fn the_otpauth_uri_carries_the_secret_and_issuer() {
+        let secret = rfc_secret();
+        let uri = secret.otpauth_uri("jane doe");
+
+        assert!(uri.starts_with("otpauth://totp/Newsletter:jane%20doe?"));
+        assert!(uri.contains(&format!("secret={}", secret.expose_secret())));
+        assert!(uri.contains("issuer=Newsletter"));
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_forgiving_to_type() {
+        let codes = generate_recovery_codes();
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+
+        let code = &codes[0];
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&format!(" {} ", code.replace('-', "").to_uppercase()))
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! src/client_ip.rs
+
+use std::net::IpAddr;
+
+use actix_web::http::header::HeaderName;
+use actix_web::web::Data;
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The reverse proxies in front of the application, whose `X-Forwarded-For` header is believed.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// The address of the client that reached us through `peer`.
+    ///
+    /// `X-Forwarded-For` is read from the right and only for as long as the hop that
+    /// appended the address is a trusted proxy: anything further left was written by the
+    /// client itself, which could pick whatever address it likes.
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: &str) -> IpAddr {
+        let mut client = peer;
+        for hop in forwarded_for.rsplit(',').map(str::trim) {
+            if !self.contains(&client) {
+                break;
+            }
+            match hop.parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+/// The address of the client that sent `request`.
+///
+/// This is the address of the peer connected to us, unless it is one of the
+/// [`TrustedProxies`] registered as application data.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    let Some(proxies) = request.app_data::<Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+
+    let forwarded_for = request
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    Some(proxies.client_ip(peer, &forwarded_for))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::TrustedProxies;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn proxies(networks: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(networks.iter().map(|n| n.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_without_trusted_proxies() {
+        let client = TrustedProxies::default().client_ip(ip("203.0.113.7"), "198.51.100.1");
+
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_addresses_from_an_untrusted_peer_are_ignored() {
+        let client = proxies(&["10.0.0.0/8"]).client_ip(ip("203.0.113.7"), "198.51.100.1");
+
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_address_appended_by_a_trusted_proxy_is_used() {
+        let client = proxies(&["10.0.0.0/8"]).client_ip(ip("10.0.0.2"), "198.51.100.1");
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn addresses_written_by_the_client_itself_are_skipped() {
+        let client = proxies(&["10.0.0.0/8"])
+            .client_ip(ip("10.0.0.2"), "192.0.2.99, 198.51.100.1, 10.0.0.1");
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn a_malformed_hop_stops_at_the_last_trusted_proxy() {
+        let client = proxies(&["10.0.0.0/8"]).client_ip(ip("10.0.0.2"), "198.51.100.1, garbage");
+
+        assert_eq!(client, ip("10.0.0.2"));
+    }
+}
@@ -1,20 +1,266 @@
 //! src/configuration.rs
 
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
 use std::time::Duration;
 
 use config::ConfigError;
+use ipnet::IpNet;
 use redact::Secret;
+use redis::IntoConnectionInfo;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
+use crate::authentication::PasswordHashing;
+use crate::client_ip::TrustedProxies;
 use crate::{domain::SubscriberEmail, email_client::EmailClient};
 
+/// Cookies and form tokens are signed with keys derived from the HMAC secret,
+/// which need at least this many bytes.
+const MIN_HMAC_SECRET_LENGTH: usize = 64;
+
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub rate_limit: RateLimitSettings,
+    pub subscription_form: SubscriptionFormSettings,
+    pub email_screening: EmailScreeningSettings,
+    pub login_lockout: LoginLockoutSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub idempotency: IdempotencySettings,
+}
+
+impl Settings {
+    /// Check every setting that would otherwise only fail once it is used, or let the
+    /// application misbehave, and report all the problems found at once.
+    pub fn validate(&self) -> Result<(), InvalidConfiguration> {
+        let mut problems = Vec::new();
+        let mut report = |setting: &str, message: String| {
+            problems.push(ConfigurationProblem {
+                setting: setting.to_owned(),
+                message,
+            })
+        };
+
+        let application = &self.application;
+        if let Err(message) = check_http_url(&application.base_url) {
+            report("application.base_url", message);
+        }
+        let hmac_secret_length = application.hmac_secret.expose_secret().len();
+        if hmac_secret_length < MIN_HMAC_SECRET_LENGTH {
+            report(
+                "application.hmac_secret",
+                format!(
+                    "must be at least {MIN_HMAC_SECRET_LENGTH} bytes long, not {hmac_secret_length}."
+                ),
+            );
+        }
+        if let Err(message) = application.trusted_proxies() {
+            report("application.trusted_proxies", message);
+        }
+
+        let database = &self.database;
+        for (setting, value) in [
+            ("database.host", &database.host),
+            ("database.username", &database.username),
+            ("database.database_name", &database.database_name),
+        ] {
+            if value.trim().is_empty() {
+                report(setting, "must not be empty.".into());
+            }
+        }
+        if database.port == 0 {
+            report("database.port", "must not be 0.".into());
+        }
+
+        let email_client = &self.email_client;
+        if let Err(message) = check_http_url(&email_client.base_url) {
+            report("email_client.base_url", message);
+        }
+        if email_client.sender().is_err() {
+            report(
+                "email_client.sender_email",
+                format!(
+                    "`{}` is not a valid email address.",
+                    email_client.sender_email
+                ),
+            );
+        }
+        if email_client.authorization_token.expose_secret().is_empty() {
+            report(
+                "email_client.authorization_token",
+                "must not be empty.".into(),
+            );
+        }
+        if email_client.timeout_milliseconds == 0 {
+            report("email_client.timeout_milliseconds", "must not be 0.".into());
+        }
+
+        if self
+            .redis_uri
+            .expose_secret()
+            .as_str()
+            .into_connection_info()
+            .is_err()
+        {
+            report(
+                "redis_uri",
+                "must be a Redis URI, such as `redis://127.0.0.1:6379`.".into(),
+            );
+        }
+
+        let rate_limit = &self.rate_limit;
+        for (route, limits) in [
+            ("subscriptions", &rate_limit.subscriptions),
+            ("login", &rate_limit.login),
+            ("confirm", &rate_limit.confirm),
+            ("password_reset", &rate_limit.password_reset),
+        ] {
+            for (dimension, quota) in [
+                ("per_ip", limits.per_ip),
+                ("per_identity", limits.per_identity),
+            ] {
+                let Some(quota) = quota else { continue };
+                if quota.max_requests == 0 {
+                    report(
+                        &format!("rate_limit.{route}.{dimension}.max_requests"),
+                        "must not be 0: leave the quota out to stop limiting requests.".into(),
+                    );
+                }
+                if quota.window_seconds == 0 {
+                    report(
+                        &format!("rate_limit.{route}.{dimension}.window_seconds"),
+                        "must not be 0.".into(),
+                    );
+                }
+            }
+        }
+
+        let subscription_form = &self.subscription_form;
+        if subscription_form.max_age_seconds <= subscription_form.min_fill_seconds {
+            report(
+                "subscription_form.max_age_seconds",
+                "must be greater than `min_fill_seconds`, or every submission is discarded.".into(),
+            );
+        }
+
+        let email_screening = &self.email_screening;
+        if email_screening.verify_mail_domain && email_screening.dns_timeout_milliseconds == 0 {
+            report(
+                "email_screening.dns_timeout_milliseconds",
+                "must not be 0 when `verify_mail_domain` is on.".into(),
+            );
+        }
+
+        if self.login_lockout.max_consecutive_failures > 0
+            && self.login_lockout.lockout_seconds == 0
+        {
+            report("login_lockout.lockout_seconds", "must not be 0.".into());
+        }
+
+        let password_policy = &self.password_policy;
+        if password_policy.min_length > password_policy.max_length {
+            report(
+                "password_policy.min_length",
+                "must not be greater than `max_length`.".into(),
+            );
+        }
+        if password_policy.min_strength > 4 {
+            report(
+                "password_policy.min_strength",
+                "must be between 0 and 4.".into(),
+            );
+        }
+
+        if let Err(error) = PasswordHashing::new(&self.password_hashing) {
+            report("password_hashing", format!("{error:#}."));
+        }
+
+        let idempotency = &self.idempotency;
+        for (setting, value) in [
+            (
+                "idempotency.retention_seconds",
+                idempotency.retention_seconds,
+            ),
+            (
+                "idempotency.cleanup_interval_seconds",
+                idempotency.cleanup_interval_seconds,
+            ),
+        ] {
+            if value == 0 {
+                report(setting, "must not be 0.".into());
+            }
+        }
+        if idempotency.cleanup_batch_size <= 0 {
+            report(
+                "idempotency.cleanup_batch_size",
+                "must be greater than 0.".into(),
+            );
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfiguration(problems))
+        }
+    }
+}
+
+/// A setting that prevents the application from starting, or from working as intended.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigurationProblem {
+    /// Where the setting lives in the configuration files, e.g. `email_client.base_url`.
+    pub setting: String,
+    pub message: String,
+}
+
+impl ConfigurationProblem {
+    /// The environment variable that overrides the setting.
+    pub fn environment_variable(&self) -> String {
+        environment_variable(&self.setting)
+    }
+}
+
+/// Every problem found by `Settings::validate`, so that they can all be fixed in one go.
+#[derive(Debug)]
+pub struct InvalidConfiguration(pub Vec<ConfigurationProblem>);
+
+impl std::error::Error for InvalidConfiguration {}
+
+impl Display for InvalidConfiguration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "The configuration is invalid:")?;
+        for problem in &self.0 {
+            writeln!(
+                f,
+                "  - {} ({}): {}",
+                problem.setting,
+                problem.environment_variable(),
+                problem.message
+            )?;
+        }
+        write!(
+            f,
+            "Fix them in the `configuration` directory or with the environment variables above."
+        )
+    }
+}
+
+/// Check that `url` is an absolute `http` or `https` URL.
+fn check_http_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        Ok(url) => Err(format!(
+            "must be an `http` or `https` URL, not `{}`.",
+            url.scheme()
+        )),
+        Err(error) => Err(format!("`{url}` is not a valid URL: {error}.")),
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -25,6 +271,48 @@ pub struct ApplicationSettings {
     pub name: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Addresses or networks of the reverse proxies in front of the application, such as
+    /// `10.0.0.0/8`. Only they are believed when they forward the client's address.
+    #[serde(default, deserialize_with = "deserialize_list_from_string")]
+    pub trusted_proxies: Vec<String>,
+}
+
+impl ApplicationSettings {
+    pub fn trusted_proxies(&self) -> Result<TrustedProxies, String> {
+        self.trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("`{proxy}` is neither an IP address nor a network."))
+            })
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies::new)
+    }
+}
+
+/// Accept a list, or a comma-separated string as environment variables can only hold strings.
+fn deserialize_list_from_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Items(Vec<String>),
+        CommaSeparated(String),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        List::Items(items) => items,
+        List::CommaSeparated(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+    })
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -46,6 +334,192 @@ pub struct EmailClientSettings {
     pub timeout_milliseconds: u64,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub backend: RateLimitBackend,
+    #[serde(default)]
+    pub subscriptions: RouteRateLimit,
+    #[serde(default)]
+    pub login: RouteRateLimit,
+    #[serde(default)]
+    pub confirm: RouteRateLimit,
+    #[serde(default)]
+    pub password_reset: RouteRateLimit,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitBackend {
+    Redis,
+    InMemory,
+}
+
+/// Bot protection for the HTML subscription form.
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionFormSettings {
+    /// Submissions posted sooner than this after the form was rendered are discarded.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_seconds: u64,
+    /// Form tokens older than this are no longer accepted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_age_seconds: u64,
+}
+
+/// Extra checks applied to subscriber email addresses on top of syntax validation.
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailScreeningSettings {
+    pub reject_disposable: bool,
+    pub suggest_typos: bool,
+    /// Replaces the bundled list of disposable domains, one domain per line.
+    #[serde(default)]
+    pub disposable_domains_file: Option<String>,
+    /// Reject domains without MX or A records.
+    pub verify_mail_domain: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub dns_timeout_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub dns_cache_ttl_seconds: u64,
+    /// How many answers are kept at most; 0 turns the cache off.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub dns_cache_max_entries: usize,
+}
+
+/// Temporarily refuse logins for a username after repeated failures.
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct LoginLockoutSettings {
+    /// How many failed attempts in a row lock the username.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_consecutive_failures: u32,
+    /// How long the lock lasts, counted from the last failed attempt.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+}
+
+impl LoginLockoutSettings {
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_seconds)
+    }
+}
+
+/// Requirements for new admin passwords.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    /// Minimum zxcvbn score, from 0 (trivially guessable) to 4 (very hard to guess).
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_strength: u8,
+    /// Replaces the bundled list of breached passwords, one password per line.
+    #[serde(default)]
+    pub breached_passwords_file: Option<String>,
+}
+
+/// Argon2id cost parameters for new password hashes.
+///
+/// Raise them as hardware gets faster: existing hashes are upgraded on their owner's next login.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+/// How long idempotency keys are remembered, and how expired records are cleaned up.
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct IdempotencySettings {
+    /// A key reused after this long is processed as a new request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_seconds: u64,
+    /// How many expired records a single cleanup query deletes, to keep its locks short.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_batch_size: i64,
+    #[serde(default)]
+    pub concurrent_requests: ConcurrentRequestSettings,
+}
+
+/// What each idempotent endpoint does with a request whose key is still being processed.
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+pub struct ConcurrentRequestSettings {
+    #[serde(default)]
+    pub newsletter_form: ConcurrentRequestHandling,
+    #[serde(default)]
+    pub newsletter_api: ConcurrentRequestHandling,
+    #[serde(default)]
+    pub subscription_form: ConcurrentRequestHandling,
+    #[serde(default)]
+    pub subscription_api: ConcurrentRequestHandling,
+    #[serde(default)]
+    pub password_form: ConcurrentRequestHandling,
+}
+
+/// Wait up to `max_wait_milliseconds` for the first request to finish and answer with its
+/// response, then give up with `409 Conflict`. Zero answers `409 Conflict` straight away.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct ConcurrentRequestHandling {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_wait_milliseconds: u64,
+    /// Sent in the `Retry-After` header of `409 Conflict` responses.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_after_seconds: u64,
+}
+
+impl Default for ConcurrentRequestHandling {
+    fn default() -> Self {
+        Self {
+            max_wait_milliseconds: 0,
+            retry_after_seconds: 1,
+        }
+    }
+}
+
+impl ConcurrentRequestHandling {
+    pub fn max_wait(&self) -> Duration {
+        Duration::from_millis(self.max_wait_milliseconds)
+    }
+
+    pub fn retry_after(&self) -> Duration {
+        Duration::from_secs(self.retry_after_seconds)
+    }
+}
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_seconds)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
+/// Quotas for a single route. A missing quota means that dimension is not limited.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct RouteRateLimit {
+    pub per_ip: Option<Quota>,
+    pub per_identity: Option<Quota>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct Quota {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+}
+
+impl Quota {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+}
+
 impl EmailClientSettings {
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
@@ -68,6 +542,16 @@ impl EmailClientSettings {
     }
 }
 
+impl EmailScreeningSettings {
+    pub fn dns_timeout(&self) -> Duration {
+        Duration::from_millis(self.dns_timeout_milliseconds)
+    }
+
+    pub fn dns_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.dns_cache_ttl_seconds)
+    }
+}
+
 impl DatabaseSettings {
     pub fn connect_options(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
@@ -84,60 +568,154 @@ impl DatabaseSettings {
             .ssl_mode(ssl_mode)
             .database(&self.database_name)
     }
-
-    pub fn connection_string(&self) -> String {
-        format!(
-            "postgres://{}:{}@{}:{}/{}?sslmode={}",
-            self.username,
-            self.password.expose_secret(),
-            self.host,
-            self.port,
-            self.database_name,
-            if self.require_ssl {
-                "require"
-            } else {
-                "prefer"
-            }
-        )
-    }
 }
 
+/// The settings holding credentials.
+///
+/// Each of them can also be read from the file named by its environment variable with
+/// a `_FILE` suffix, e.g. `APP_DATABASE__PASSWORD_FILE`, which is how Docker and
+/// Kubernetes hand secrets over. The values in `base.yaml` are placeholders.
+const SECRET_SETTINGS: [&str; 4] = [
+    "application.hmac_secret",
+    "database.password",
+    "email_client.authorization_token",
+    "redis_uri",
+];
+
 pub struct Configuration;
 
 impl Configuration {
     pub fn get() -> Result<Settings, ConfigError> {
-        let base_path = std::env::current_dir().expect("Failed to determine the current directory");
-        let configuration_directory = base_path.join("configuration");
+        let environment = Self::environment(std::env::var("APP_ENVIRONMENT").ok())
+            .map_err(|problems| ConfigError::Foreign(Box::new(problems)))?;
 
-        // Detect the running environment.
-        // Default to `local` if not specified.
-        let environment: Environment = std::env::var("APP_ENVIRONMENT")
-            .unwrap_or_else(|_| "local".into())
+        Self::for_environment(environment)
+    }
+
+    /// The running environment, named by `APP_ENVIRONMENT`; `local` if it is not set.
+    fn environment(variable: Option<String>) -> Result<Environment, InvalidConfiguration> {
+        variable
+            .unwrap_or_else(|| "local".into())
             .try_into()
-            .expect("Failed to parse APP_ENVIRONMENT");
+            .map_err(|message| {
+                InvalidConfiguration(vec![ConfigurationProblem {
+                    setting: "environment".into(),
+                    message,
+                }])
+            })
+    }
+
+    pub fn for_environment(environment: Environment) -> Result<Settings, ConfigError> {
+        Self::for_environment_with(environment, std::env::vars().collect())
+    }
+
+    /// Read the settings of `environment`, overridden by the `APP_` variables among
+    /// `variables` rather than by those of the process.
+    ///
+    /// Staging and production refuse to start with the placeholder secrets of `base.yaml`:
+    /// both are reachable by others, and the placeholders are public.
+    pub fn for_environment_with(
+        environment: Environment,
+        variables: HashMap<String, String>,
+    ) -> Result<Settings, ConfigError> {
+        let base_path = std::env::current_dir().expect("Failed to determine the current directory");
+        let configuration_directory = base_path.join("configuration");
+        let base_file = || config::File::from(configuration_directory.join("base.yaml"));
 
         let environment_filename = format!("{}.yaml", environment.as_str());
 
-        let settings = config::Config::builder()
-            .add_source(config::File::from(
-                configuration_directory.join("base.yaml"),
-            ))
+        let mut builder = config::Config::builder()
+            .add_source(base_file())
             .add_source(config::File::from(
                 configuration_directory.join(environment_filename),
             ))
             .add_source(
                 config::Environment::with_prefix("APP")
                     .prefix_separator("_")
-                    .separator("__"),
-            )
-            .build()?;
+                    .separator("__")
+                    .source(Some(variables.clone())),
+            );
+        for (setting, secret) in read_secret_files(|variable| variables.get(variable).cloned())? {
+            builder = builder.set_override(setting, secret)?;
+        }
+        let settings = builder.build()?;
+
+        if matches!(environment, Environment::Staging | Environment::Production) {
+            let placeholders = config::Config::builder().add_source(base_file()).build()?;
+            let unset: Vec<_> = SECRET_SETTINGS
+                .into_iter()
+                .filter(|setting| {
+                    placeholders.get_string(setting).ok() == settings.get_string(setting).ok()
+                })
+                .collect();
+            if !unset.is_empty() {
+                return Err(ConfigError::Message(placeholder_secrets_report(
+                    environment,
+                    &unset,
+                )));
+            }
+        }
 
         settings.try_deserialize::<Settings>()
     }
 }
 
+/// Read the secrets whose `_FILE` environment variable is set, looked up with `var`.
+fn read_secret_files(
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(&'static str, String)>, ConfigError> {
+    let mut secrets = Vec::new();
+
+    for setting in SECRET_SETTINGS {
+        let variable = environment_variable(setting);
+        let file_variable = format!("{variable}_FILE");
+        let Some(path) = var(&file_variable) else {
+            continue;
+        };
+        if var(&variable).is_some() {
+            return Err(ConfigError::Message(format!(
+                "Set either {variable} or {file_variable}, not both."
+            )));
+        }
+
+        let secret = std::fs::read_to_string(&path).map_err(|error| {
+            ConfigError::Message(format!(
+                "Failed to read {file_variable} from `{path}`: {error}"
+            ))
+        })?;
+        // Files written by editors and `echo` end with a newline that is not part of the secret.
+        secrets.push((setting, secret.trim_end_matches(['\r', '\n']).to_owned()));
+    }
+
+    Ok(secrets)
+}
+
+fn placeholder_secrets_report(environment: Environment, settings: &[&str]) -> String {
+    let mut report = format!(
+        "The {} environment cannot run with the placeholder secrets of base.yaml:",
+        environment.as_str()
+    );
+    for setting in settings {
+        let variable = environment_variable(setting);
+        report.push_str(&format!(
+            "\n  - {setting}: set {variable} or {variable}_FILE."
+        ));
+    }
+    report
+}
+
+/// The environment variable that overrides `setting`, e.g. `APP_DATABASE__PASSWORD`
+/// for `database.password`.
+fn environment_variable(setting: &str) -> String {
+    format!("APP_{}", setting.replace('.', "__").to_uppercase())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Environment {
     Local,
+    /// Used by the test suite.
+    Test,
+    Staging,
     Production,
 }
 
@@ -145,6 +723,8 @@ impl Environment {
     pub fn as_str(&self) -> &'static str {
         match self {
             Environment::Local => "local",
+            Environment::Test => "test",
+            Environment::Staging => "staging",
             Environment::Production => "production",
         }
     }
@@ -156,11 +736,220 @@ impl TryFrom<String> for Environment {
     fn try_from(string: String) -> Result<Self, Self::Error> {
         match string.to_lowercase().as_str() {
             "local" => Ok(Self::Local),
+            "test" => Ok(Self::Test),
+            "staging" => Ok(Self::Staging),
             "production" => Ok(Self::Production),
             other => Err(format!(
-                "{} is not a supported environment. Use either `local` or `production`.",
+                "{} is not a supported environment. \
+                 Use either `local`, `test`, `staging` or `production`.",
                 other
             )),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use redact::Secret;
+
+    use super::{read_secret_files, Configuration, Environment, Settings};
+
+    fn settings() -> Settings {
+        Configuration::for_environment(Environment::Local).expect("Failed to read configuration.")
+    }
+
+    fn variables(variables: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let variables: HashMap<String, String> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| variables.get(name).cloned()
+    }
+
+    fn invalid_settings(settings: Settings) -> Vec<String> {
+        settings
+            .validate()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|problem| problem.setting)
+            .collect()
+    }
+
+    #[test]
+    fn the_local_configuration_is_valid() {
+        assert!(settings().validate().is_ok());
+    }
+
+    #[test]
+    fn trusted_proxies_are_addresses_or_networks() {
+        let mut settings = settings();
+        settings.application.trusted_proxies = vec!["10.0.0.0/8".into(), "192.0.2.1".into()];
+        assert!(settings.validate().is_ok());
+
+        settings.application.trusted_proxies = vec!["10.0.0.0/33".into()];
+        assert_eq!(invalid_settings(settings), ["application.trusted_proxies"]);
+    }
+
+    #[test]
+    fn trusted_proxies_can_be_set_as_a_comma_separated_string() {
+        let settings = config::Config::builder()
+            .add_source(config::File::from_str(
+                "trusted_proxies: \"10.0.0.0/8, 192.0.2.1\"",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap();
+
+        #[derive(serde::Deserialize)]
+        struct Proxies {
+            #[serde(deserialize_with = "super::deserialize_list_from_string")]
+            trusted_proxies: Vec<String>,
+        }
+        let proxies: Proxies = settings.try_deserialize().unwrap();
+
+        assert_eq!(proxies.trusted_proxies, ["10.0.0.0/8", "192.0.2.1"]);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut settings = settings();
+        settings.application.hmac_secret = Secret::new("too-short".into());
+        settings.email_client.sender_email = "not-an-email".into();
+        settings.email_client.timeout_milliseconds = 0;
+        settings.redis_uri = Secret::new("not a uri".into());
+
+        assert_eq!(
+            invalid_settings(settings),
+            [
+                "application.hmac_secret",
+                "email_client.sender_email",
+                "email_client.timeout_milliseconds",
+                "redis_uri",
+            ]
+        );
+    }
+
+    #[test]
+    fn base_urls_must_be_absolute_http_urls() {
+        let mut settings = settings();
+        settings.application.base_url = "127.0.0.1".into();
+        settings.email_client.base_url = "ftp://127.0.0.1".into();
+
+        assert_eq!(
+            invalid_settings(settings),
+            ["application.base_url", "email_client.base_url"]
+        );
+    }
+
+    #[test]
+    fn an_unsupported_environment_is_reported_as_a_configuration_problem() {
+        assert_eq!(
+            Configuration::environment(None).unwrap(),
+            Environment::Local
+        );
+
+        let problems = Configuration::environment(Some("prod".into())).unwrap_err();
+
+        assert_eq!(problems.0.len(), 1);
+        assert_eq!(problems.0[0].environment_variable(), "APP_ENVIRONMENT");
+    }
+
+    #[test]
+    fn the_report_names_the_environment_variable_of_each_setting() {
+        let mut settings = settings();
+        settings.email_client.sender_email = "not-an-email".into();
+
+        let report = settings.validate().unwrap_err().to_string();
+
+        assert!(report.contains("email_client.sender_email (APP_EMAIL_CLIENT__SENDER_EMAIL): "));
+        assert!(!report.contains("my-secret-token"));
+    }
+
+    #[test]
+    fn secrets_are_read_from_the_files_named_by_their_file_variables() {
+        let path = std::env::temp_dir().join(format!("{}.secret", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "from-a-file\n").unwrap();
+
+        let secrets = read_secret_files(variables(&[(
+            "APP_DATABASE__PASSWORD_FILE",
+            path.to_str().unwrap(),
+        )]))
+        .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(secrets, [("database.password", "from-a-file".to_owned())]);
+    }
+
+    #[test]
+    fn a_secret_cannot_be_set_both_directly_and_from_a_file() {
+        let error = read_secret_files(variables(&[
+            ("APP_REDIS_URI", "redis://redis:6379"),
+            ("APP_REDIS_URI_FILE", "/run/secrets/redis_uri"),
+        ]))
+        .unwrap_err();
+
+        assert!(error.to_string().contains("APP_REDIS_URI_FILE"));
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_an_error() {
+        let error = read_secret_files(variables(&[(
+            "APP_APPLICATION__HMAC_SECRET_FILE",
+            "/does/not/exist",
+        )]))
+        .unwrap_err();
+
+        assert!(error.to_string().contains("/does/not/exist"));
+    }
+
+    #[test]
+    fn production_and_staging_refuse_to_run_with_the_placeholder_secrets() {
+        for environment in [Environment::Staging, Environment::Production] {
+            let error = Configuration::for_environment_with(environment, HashMap::new())
+                .err()
+                .expect("Ran with placeholder secrets")
+                .to_string();
+
+            assert!(error.contains(environment.as_str()));
+            assert!(error.contains("application.hmac_secret: set APP_APPLICATION__HMAC_SECRET"));
+            assert!(error.contains("database.password"));
+            assert!(!error.contains("long-and-very-secret"));
+        }
+    }
+
+    #[test]
+    fn production_runs_once_every_secret_is_set() {
+        let variables = [
+            ("APP_APPLICATION__BASE_URL", "https://example.com"),
+            ("APP_APPLICATION__HMAC_SECRET", "a-real-hmac-secret"),
+            ("APP_DATABASE__PASSWORD", "a-real-password"),
+            ("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN", "a-real-token"),
+            ("APP_REDIS_URI", "redis://redis:6379"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+
+        let settings =
+            Configuration::for_environment_with(Environment::Production, variables).unwrap();
+
+        assert_eq!(
+            settings.database.password.expose_secret(),
+            "a-real-password"
+        );
+    }
+
+    #[test]
+    fn every_environment_has_a_configuration_file() {
+        for name in ["local", "test", "staging", "production"] {
+            let environment = Environment::try_from(name.to_owned()).unwrap();
+            assert_eq!(environment.as_str(), name);
+            assert!(std::path::Path::new("configuration")
+                .join(format!("{name}.yaml"))
+                .exists());
+        }
+    }
+}
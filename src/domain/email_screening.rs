@@ -0,0 +1,337 @@
+//! src/domain/email_screening.rs
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context;
+
+use super::SubscriberEmail;
+use crate::configuration::EmailScreeningSettings;
+use crate::mail_domain::{DnsMailDomainResolver, MailDomainCheck, MailDomainResolver};
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// Widely used mailbox providers. A domain that is a single keystroke away from one of
+/// these is most likely a typo.
+const POPULAR_DOMAINS: &[&str] = &[
+    "aol.com",
+    "att.net",
+    "comcast.net",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "googlemail.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.fr",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "web.de",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.fr",
+    "ymail.com",
+];
+
+/// Real mailbox providers that are a keystroke away from a popular one,
+/// and must not be mistaken for a typo of it.
+const OTHER_PROVIDERS: &[&str] = &["email.com", "uol.com"];
+
+/// Optional checks applied on top of `SubscriberEmail::parse` before a subscriber is stored.
+pub struct EmailScreening {
+    disposable_domains: HashSet<String>,
+    reject_disposable: bool,
+    suggest_typos: bool,
+    mail_domain_check: Option<MailDomainCheck>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmailRejection {
+    Disposable { domain: String },
+    LikelyTypo { suggestion: String },
+    NoMailServer { domain: String },
+}
+
+impl std::fmt::Display for EmailRejection {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disposable { domain } => write!(
+                formatter,
+                "Addresses from {} are disposable and cannot be subscribed.",
+                domain
+            ),
+            Self::NoMailServer { domain } => {
+                write!(formatter, "{} does not accept email.", domain)
+            }
+            Self::LikelyTypo { suggestion } => {
+                write!(
+                    formatter,
+                    "The email domain looks mistyped. Did you mean {}?",
+                    suggestion
+                )
+            }
+        }
+    }
+}
+
+impl EmailRejection {
+    /// A corrected address the subscriber may have meant.
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            Self::Disposable { .. } | Self::NoMailServer { .. } => None,
+            Self::LikelyTypo { suggestion } => Some(suggestion),
+        }
+    }
+}
+
+impl EmailScreening {
+    /// Load the disposable domain list from `disposable_domains_file` if set,
+    /// falling back to the list bundled with the application.
+    ///
+    /// Mail domains are looked up with `resolver` if given, or else with the DNS servers of
+    /// the host, whose configuration is only read when `verify_mail_domain` is enabled.
+    pub fn from_settings(
+        settings: &EmailScreeningSettings,
+        resolver: Option<Arc<dyn MailDomainResolver>>,
+    ) -> Result<Self, anyhow::Error> {
+        let disposable_domains = match &settings.disposable_domains_file {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the disposable domains from {}", path))?,
+            None => BUNDLED_DISPOSABLE_DOMAINS.to_owned(),
+        };
+
+        Ok(Self {
+            disposable_domains: parse_domain_list(&disposable_domains),
+            reject_disposable: settings.reject_disposable,
+            suggest_typos: settings.suggest_typos,
+            mail_domain_check: match settings.verify_mail_domain {
+                true => Some(MailDomainCheck::new(
+                    match resolver {
+                        Some(resolver) => resolver,
+                        None => Arc::new(DnsMailDomainResolver::from_system_conf()?),
+                    },
+                    settings.dns_timeout(),
+                    settings.dns_cache_ttl(),
+                    settings.dns_cache_max_entries,
+                )),
+                false => None,
+            },
+        })
+    }
+
+    /// `keep_email` skips the typo check, for subscribers who were shown a suggestion and
+    /// confirmed that their address is spelled the way they meant it.
+    pub async fn screen(
+        &self,
+        email: &SubscriberEmail,
+        keep_email: bool,
+    ) -> Result<(), EmailRejection> {
+        let domain = email.domain();
+
+        if self.reject_disposable && self.is_disposable(domain) {
+            return Err(EmailRejection::Disposable {
+                domain: domain.to_owned(),
+            });
+        }
+
+        if self.suggest_typos && !keep_email {
+            if let Some(corrected) = suggest_domain(domain) {
+                return Err(EmailRejection::LikelyTypo {
+                    suggestion: format!("{}@{}", email.local_part(), corrected),
+                });
+            }
+        }
+
+        if let Some(check) = &self.mail_domain_check {
+            if !check.accepts_mail(domain).await {
+                return Err(EmailRejection::NoMailServer {
+                    domain: domain.to_owned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subdomains of a disposable domain are disposable as well.
+    fn is_disposable(&self, domain: &str) -> bool {
+        std::iter::successors(Some(domain), |domain| {
+            domain.split_once('.').map(|(_, rest)| rest)
+        })
+        .any(|candidate| self.disposable_domains.contains(candidate))
+    }
+}
+
+impl Default for EmailScreening {
+    fn default() -> Self {
+        Self {
+            disposable_domains: parse_domain_list(BUNDLED_DISPOSABLE_DOMAINS),
+            reject_disposable: true,
+            suggest_typos: true,
+            mail_domain_check: None,
+        }
+    }
+}
+
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The popular domain `domain` is a single edit away from, if any.
+///
+/// Allowing more edits flags real providers such as `yahoo.de` or `qq.com` as typos.
+fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if POPULAR_DOMAINS.contains(&domain) || OTHER_PROVIDERS.contains(&domain) {
+        return None;
+    }
+
+    POPULAR_DOMAINS
+        .iter()
+        .filter(|candidate| !is_other_country(domain, candidate))
+        .find(|candidate| edit_distance(domain, candidate) == 1)
+        .copied()
+}
+
+/// Whether the domains are the same provider in two countries, e.g. `yahoo.gr` and `yahoo.fr`.
+fn is_other_country(domain: &str, candidate: &str) -> bool {
+    match (domain.rsplit_once('.'), candidate.rsplit_once('.')) {
+        (Some((name, country)), Some((candidate_name, candidate_country))) => {
+            name == candidate_name && country.len() == 2 && candidate_country.len() == 2
+        }
+        _ => false,
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// transpositions of adjacent characters all count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailRejection, EmailScreening};
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_err_eq, assert_ok};
+
+    async fn screen(email: &str) -> Result<(), EmailRejection> {
+        let email = SubscriberEmail::parse(email.to_string()).unwrap();
+        EmailScreening::default().screen(&email, false).await
+    }
+
+    #[tokio::test]
+    async fn addresses_at_popular_providers_are_accepted() {
+        for email in ["ursula@gmail.com", "ursula@mail.com", "ursula@ymail.com"] {
+            assert_ok!(screen(email).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn real_providers_close_to_popular_ones_are_accepted() {
+        for domain in [
+            "aim.com",
+            "email.com",
+            "gmx.net",
+            "hey.com",
+            "hotmail.it",
+            "qq.com",
+            "uol.com",
+            "yahoo.ca",
+            "yahoo.de",
+            "yahoo.gr",
+        ] {
+            assert_ok!(screen(&format!("ursula@{domain}")).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_can_keep_an_address_that_looks_mistyped() {
+        let email = SubscriberEmail::parse("ursula@gmial.com".to_string()).unwrap();
+
+        assert_ok!(EmailScreening::default().screen(&email, true).await);
+    }
+
+    #[tokio::test]
+    async fn addresses_at_unknown_domains_are_accepted() {
+        assert_ok!(screen("ursula@earthsea.org").await);
+    }
+
+    #[tokio::test]
+    async fn disposable_domains_and_their_subdomains_are_rejected() {
+        for email in ["ursula@mailinator.com", "ursula@inbox.mailinator.com"] {
+            assert!(matches!(
+                screen(email).await,
+                Err(EmailRejection::Disposable { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn common_typos_come_with_a_suggestion() {
+        let cases = [
+            ("Ursula@gmial.com", "Ursula@gmail.com"),
+            ("ursula@gmail.con", "ursula@gmail.com"),
+            ("ursula@hotmial.com", "ursula@hotmail.com"),
+            ("ursula@yaho.com", "ursula@yahoo.com"),
+            ("ursula@outlok.com", "ursula@outlook.com"),
+        ];
+
+        for (email, suggestion) in cases {
+            assert_err_eq!(
+                screen(email).await,
+                EmailRejection::LikelyTypo {
+                    suggestion: suggestion.to_string()
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_can_be_turned_off() {
+        let screening = EmailScreening {
+            reject_disposable: false,
+            suggest_typos: false,
+            ..EmailScreening::default()
+        };
+
+        for email in ["ursula@mailinator.com", "ursula@gmial.com"] {
+            let email = SubscriberEmail::parse(email.to_string()).unwrap();
+            assert_ok!(screening.screen(&email, false).await);
+        }
+    }
+}
@@ -1,7 +1,17 @@
+mod email_screening;
+mod form_token;
 mod new_subscriber;
+mod password_policy;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_consent;
 
+pub use email_screening::{EmailRejection, EmailScreening};
+pub use form_token::FormToken;
 pub use new_subscriber::NewSubscriber;
+pub use password_policy::{PasswordPolicy, PasswordRejection};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_consent::{
+    ConsentSource, SubscriptionConsent, CONSENT_TEXT, CONSENT_TEXT_VERSION,
+};
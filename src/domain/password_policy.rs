@@ -0,0 +1,201 @@
+//! src/domain/password_policy.rs
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+use redact::Secret;
+
+use crate::configuration::PasswordPolicySettings;
+
+const BUNDLED_BREACHED_PASSWORDS: &str = include_str!("breached_passwords.txt");
+
+/// Rules every new admin password must follow.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    min_strength: u8,
+    breached_passwords: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordRejection {
+    TooShort { min_length: usize },
+    TooLong { max_length: usize },
+    Breached,
+    TooWeak,
+}
+
+impl std::fmt::Display for PasswordRejection {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort { min_length } => write!(
+                formatter,
+                "The new password must be at least {} characters long.",
+                min_length
+            ),
+            Self::TooLong { max_length } => write!(
+                formatter,
+                "The new password must be at most {} characters long.",
+                max_length
+            ),
+            Self::Breached => write!(
+                formatter,
+                "The new password appears in a list of breached passwords. Please choose another one."
+            ),
+            Self::TooWeak => write!(
+                formatter,
+                "The new password is too easy to guess. Try a longer passphrase made of unrelated words."
+            ),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Load the breached password corpus from `breached_passwords_file` if set,
+    /// falling back to the list bundled with the application.
+    pub fn from_settings(settings: &PasswordPolicySettings) -> Result<Self, anyhow::Error> {
+        let breached_passwords = match &settings.breached_passwords_file {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the breached passwords from {}", path))?,
+            None => BUNDLED_BREACHED_PASSWORDS.to_owned(),
+        };
+
+        Ok(Self {
+            min_length: settings.min_length,
+            max_length: settings.max_length,
+            min_strength: settings.min_strength,
+            breached_passwords: parse_password_list(&breached_passwords),
+        })
+    }
+
+    pub fn check(&self, password: &Secret<String>) -> Result<(), PasswordRejection> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            return Err(PasswordRejection::TooShort {
+                min_length: self.min_length,
+            });
+        }
+        if length > self.max_length {
+            return Err(PasswordRejection::TooLong {
+                max_length: self.max_length,
+            });
+        }
+        if self.breached_passwords.contains(&password.to_lowercase()) {
+            return Err(PasswordRejection::Breached);
+        }
+        if self.strength(password) < self.min_strength {
+            return Err(PasswordRejection::TooWeak);
+        }
+
+        Ok(())
+    }
+
+    /// zxcvbn's score, from 0 (trivially guessable) to 4 (very hard to guess).
+    pub fn strength(&self, password: &str) -> u8 {
+        zxcvbn::zxcvbn(password, &[]).score().into()
+    }
+}
+
+fn parse_password_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use redact::Secret;
+
+    use super::{PasswordPolicy, PasswordRejection};
+    use crate::configuration::PasswordPolicySettings;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::from_settings(&PasswordPolicySettings {
+            min_length: 12,
+            max_length: 64,
+            min_strength: 3,
+            breached_passwords_file: None,
+        })
+        .unwrap()
+    }
+
+    fn check(password: &str) -> Result<(), PasswordRejection> {
+        policy().check(&Secret::new(password.to_owned()))
+    }
+
+    #[test]
+    fn short_passwords_are_rejected() {
+        assert_eq!(
+            check("Tr0ub4dor&3"),
+            Err(PasswordRejection::TooShort { min_length: 12 })
+        );
+        assert_eq!(
+            check(""),
+            Err(PasswordRejection::TooShort { min_length: 12 })
+        );
+    }
+
+    #[test]
+    fn long_passwords_are_rejected() {
+        assert_eq!(
+            check(&"correct horse battery staple ".repeat(3)),
+            Err(PasswordRejection::TooLong { max_length: 64 })
+        );
+    }
+
+    #[test]
+    fn length_is_counted_in_characters_not_bytes() {
+        assert!(check("ééééééééééé").is_err());
+        assert!(check("grüße aus köln am rhein").is_ok());
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected_regardless_of_case() {
+        assert_eq!(check("LeaveMeAlone"), Err(PasswordRejection::Breached));
+        assert_eq!(check("qwerty123456"), Err(PasswordRejection::Breached));
+    }
+
+    #[test]
+    fn predictable_passwords_are_too_weak() {
+        assert_eq!(check("aaaaaaaaaaaaaaaa"), Err(PasswordRejection::TooWeak));
+        assert_eq!(check("abcdefghijklmnop"), Err(PasswordRejection::TooWeak));
+        assert_eq!(check("9876543210987"), Err(PasswordRejection::TooWeak));
+        assert_eq!(check("password12345"), Err(PasswordRejection::TooWeak));
+    }
+
+    #[test]
+    fn passphrases_and_random_strings_are_accepted() {
+        assert_eq!(check("correct horse battery staple"), Ok(()));
+        assert_eq!(check("f7Kq9zLm2Xv8"), Ok(()));
+        assert_eq!(check("everythinghastostartsomewhere"), Ok(()));
+    }
+
+    #[test]
+    fn the_breached_corpus_can_be_replaced() {
+        let path = std::env::temp_dir().join(format!("breached-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# fixture\ncorrect horse battery staple\n").unwrap();
+
+        let policy = PasswordPolicy::from_settings(&PasswordPolicySettings {
+            min_length: 12,
+            max_length: 64,
+            min_strength: 3,
+            breached_passwords_file: Some(path.to_string_lossy().into_owned()),
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            policy.check(&Secret::new("correct horse battery staple".into())),
+            Err(PasswordRejection::Breached)
+        );
+        // No longer listed as breached, though zxcvbn still knows how common it is.
+        assert_eq!(
+            policy.check(&Secret::new("qwerty123456".into())),
+            Err(PasswordRejection::TooWeak)
+        );
+    }
+}
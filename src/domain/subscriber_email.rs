@@ -6,12 +6,33 @@ use validator::ValidateEmail;
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// Validate an email address, trimming surrounding whitespace and lowercasing
+    /// the domain, which is case-insensitive.
+    ///
+    /// The local part is kept as typed since the mail server it belongs to may treat it
+    /// as case-sensitive. The database still compares addresses regardless of case, so
+    /// that `Alice@` and `alice@` do not become two subscribers who get every issue twice.
     pub fn parse(input: String) -> Result<Self, String> {
-        if input.validate_email() {
-            return Ok(Self(input));
+        let trimmed = input.trim();
+
+        if !trimmed.validate_email() {
+            return Err(format!("{} is not a valid subscriber email.", input));
         }
 
-        Err(format!("{} is not a valid subscriber email.", input))
+        let (local_part, domain) = trimmed
+            .rsplit_once('@')
+            .ok_or_else(|| format!("{} is not a valid subscriber email.", input))?;
+        Ok(Self(format!("{}@{}", local_part, domain.to_lowercase())))
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map_or("", |(local_part, _)| local_part)
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
     }
 }
 
@@ -32,7 +53,7 @@ impl std::fmt::Display for SubscriberEmail {
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claims::assert_err;
+    use claims::{assert_err, assert_ok};
 
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
@@ -74,4 +95,20 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = assert_ok!(SubscriberEmail::parse("  ursula@domain.com\n".to_string()));
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn only_the_domain_is_lowercased() {
+        let email = assert_ok!(SubscriberEmail::parse(
+            "Ursula.LeGuin@Domain.COM".to_string()
+        ));
+        assert_eq!(email.as_ref(), "Ursula.LeGuin@domain.com");
+        assert_eq!(email.local_part(), "Ursula.LeGuin");
+        assert_eq!(email.domain(), "domain.com");
+    }
 }
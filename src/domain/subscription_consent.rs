@@ -0,0 +1,55 @@
+//! src/domain/subscription_consent.rs
+
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+
+use crate::client_ip::client_ip;
+
+/// Consent wording shown next to the subscription form.
+pub const CONSENT_TEXT: &str = "By subscribing you agree to receive our newsletter by email. \
+    You can unsubscribe at any time using the link at the bottom of every issue.";
+
+/// Version identifier of [`CONSENT_TEXT`].
+/// Bump it whenever the text changes so stored consents keep pointing at what was shown.
+pub const CONSENT_TEXT_VERSION: &str = "2024-11-01";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentSource {
+    Form,
+    Api,
+}
+
+impl ConsentSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentSource::Form => "form",
+            ConsentSource::Api => "api",
+        }
+    }
+}
+
+/// How and where a subscriber gave their consent to receive our newsletter.
+#[derive(Debug)]
+pub struct SubscriptionConsent {
+    pub source: ConsentSource,
+    pub text_version: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SubscriptionConsent {
+    pub fn from_request(request: &HttpRequest, source: ConsentSource) -> Self {
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        Self {
+            source,
+            text_version: CONSENT_TEXT_VERSION.to_owned(),
+            ip: client_ip(request).map(|ip| ip.to_string()),
+            user_agent,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! src/error.rs
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use tracing_actix_web::RequestId;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// A single invalid input, reported as an `invalid_params` member of a problem document.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InvalidParam {
+    pub name: &'static str,
+    pub reason: String,
+    /// A corrected value the client may have meant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl InvalidParam {
+    pub fn new(name: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            name,
+            reason: reason.into(),
+            suggestion: None,
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+/// Client-facing description of an error, following RFC 7807.
+///
+/// Every error response carries one in its extensions so that `render_problem_details`
+/// can turn it into `application/problem+json` for clients that asked for JSON.
+/// Only what is stored here is ever shown to clients; the cause chain is logged instead.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub status: StatusCode,
+    pub problem_type: &'static str,
+    pub detail: Option<String>,
+    pub invalid_params: Vec<InvalidParam>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            problem_type: "about:blank",
+            detail: None,
+            invalid_params: Vec::new(),
+        }
+    }
+
+    pub fn with_type(mut self, problem_type: &'static str) -> Self {
+        self.problem_type = problem_type;
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_invalid_params(mut self, invalid_params: Vec<InvalidParam>) -> Self {
+        self.invalid_params = invalid_params;
+        self
+    }
+
+    pub fn title(&self) -> &'static str {
+        self.status.canonical_reason().unwrap_or("Unknown error")
+    }
+
+    /// Attach the problem to an HTML or redirect response.
+    pub fn attach_to(self, mut response: HttpResponse) -> HttpResponse {
+        response.extensions_mut().insert(self);
+        response
+    }
+
+    /// The response served to clients that did not ask for JSON: a plain text body
+    /// containing nothing but the public detail.
+    pub fn into_response(self) -> HttpResponse {
+        let body = self.detail.clone().unwrap_or_default();
+        let response = HttpResponse::build(self.status)
+            .content_type(header::ContentType::plaintext())
+            .body(body);
+        self.attach_to(response)
+    }
+
+    fn to_json(&self, instance: &str, request_id: Option<String>) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "type": self.problem_type,
+            "title": self.title(),
+            "status": self.status.as_u16(),
+            "instance": instance,
+        });
+        if let Some(detail) = &self.detail {
+            body["detail"] = detail.clone().into();
+        }
+        if let Some(request_id) = request_id {
+            body["request_id"] = request_id.into();
+        }
+        if !self.invalid_params.is_empty() {
+            body["invalid_params"] = serde_json::json!(self.invalid_params);
+        }
+        body
+    }
+}
+
+/// The shared error type for request handlers that do not need a dedicated enum.
+///
+/// `Display` only ever shows the public detail, while `Debug` walks the whole cause
+/// chain so that it ends up in the logs.
+pub struct AppError {
+    problem: Problem,
+    source: anyhow::Error,
+}
+
+impl AppError {
+    pub fn new(problem: Problem, source: impl Into<anyhow::Error>) -> Self {
+        Self {
+            problem,
+            source: source.into(),
+        }
+    }
+
+    pub fn bad_request(source: impl Into<anyhow::Error>) -> Self {
+        let source = source.into();
+        let problem = Problem::new(StatusCode::BAD_REQUEST).with_detail(source.to_string());
+        Self::new(problem, source)
+    }
+
+    pub fn internal(source: impl Into<anyhow::Error>) -> Self {
+        Self::new(Problem::new(StatusCode::INTERNAL_SERVER_ERROR), source)
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem.detail {
+            Some(detail) => write!(formatter, "{}", detail),
+            None => write!(formatter, "{}", self.problem.title()),
+        }
+    }
+}
+
+impl std::fmt::Debug for AppError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(formatter, "{}\n", self)?;
+        for cause in self.source.chain() {
+            writeln!(formatter, "Caused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        self.problem.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.problem.clone().into_response()
+    }
+}
+
+/// Render error responses as `application/problem+json` for clients that asked for JSON
+/// and tag every response with the request id generated by `TracingLogger`.
+///
+/// It must be registered *inside* `TracingLogger` to have access to the request id.
+pub async fn render_problem_details(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let request_id = req.extensions().get::<RequestId>().map(ToString::to_string);
+
+    // Middleware that wants its errors rendered must return them as responses
+    // (e.g. via `ServiceRequest::error_response`) rather than as `Err`.
+    let (request, mut response) = next.call(req).await?.map_into_boxed_body().into_parts();
+
+    if let Some(request_id) = &request_id {
+        if let Ok(value) = HeaderValue::from_str(request_id) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+    }
+
+    if !wants_json(&request) {
+        return Ok(ServiceResponse::new(request, response));
+    }
+
+    let problem = response.extensions().get::<Problem>().cloned().or_else(|| {
+        let status = response.status();
+        (status.is_client_error() || status.is_server_error()).then(|| Problem::new(status))
+    });
+
+    let response = match problem {
+        Some(problem) => {
+            let body = problem.to_json(request.path(), request_id);
+            let mut response = response.set_body(BoxBody::new(body.to_string()));
+            *response.status_mut() = problem.status;
+            let headers = response.headers_mut();
+            // A JSON client has no use for the HTML fallback redirect.
+            headers.remove(header::LOCATION);
+            headers.remove(header::CONTENT_LENGTH);
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+            response
+        }
+        None => response,
+    };
+
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// Whether the client would rather receive JSON than HTML.
+pub fn wants_json(request: &HttpRequest) -> bool {
+    let is_json = |value: Option<&HeaderValue>| {
+        value
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.contains("json"))
+            .unwrap_or(false)
+    };
+
+    is_json(request.headers().get(ACCEPT)) || is_json(request.headers().get(CONTENT_TYPE))
+}
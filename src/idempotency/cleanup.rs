@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::configuration::{IdempotencySettings, Settings};
+use crate::startup::Application;
+
+pub async fn run_cleanup_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = Application::db_connection_pool(&configuration.database);
+
+    cleanup_loop(connection_pool, configuration.idempotency).await
+}
+
+async fn cleanup_loop(pool: PgPool, settings: IdempotencySettings) -> Result<(), anyhow::Error> {
+    loop {
+        // Try again on the next round.
+        if let Err(error) =
+            delete_expired_records(&pool, settings.retention(), settings.cleanup_batch_size).await
+        {
+            tracing::error!(
+                error.cause_chain = ?error,
+                error.message = %error,
+                "Failed to delete expired idempotency records"
+            );
+        }
+        tokio::time::sleep(settings.cleanup_interval()).await;
+    }
+}
+
+/// Delete the idempotency records older than `retention`, `batch_size` rows at a time,
+/// returning how many were deleted.
+///
+/// Rows locked by a request that is reusing its key right now are skipped: they will
+/// either be reset by that request or deleted on the next round.
+#[tracing::instrument(name = "Delete expired idempotency records", skip(pool))]
+pub async fn delete_expired_records(
+    pool: &PgPool,
+    retention: Duration,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let mut n_deleted = 0;
+
+    loop {
+        let n_deleted_in_batch = sqlx::query!(
+            r#"
+            DELETE FROM idempotency
+            WHERE (scope, idempotency_key) IN (
+                SELECT scope, idempotency_key
+                FROM idempotency
+                WHERE created_at < now() - make_interval(secs => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            retention.as_secs_f64(),
+            batch_size
+        )
+        .execute(pool)
+        .await
+        .context("Failed to delete expired idempotency records.")?
+        .rows_affected();
+
+        n_deleted += n_deleted_in_batch;
+        // `batch_size` is validated to be positive, so an empty batch ends the loop too.
+        if n_deleted_in_batch < batch_size as u64 {
+            return Ok(n_deleted);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+/// A hash of what a request asks for, stored next to its idempotency key so that the
+/// key cannot be reused for a different request.
+///
+/// Requests are normalised by hashing their deserialized form re-encoded as JSON with
+/// sorted keys: field order, whitespace and fields that are not part of the request
+/// (such as the key itself) do not change the fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    pub fn of(request: &impl serde::Serialize) -> Result<Self, anyhow::Error> {
+        // Going through `Value` sorts object keys, whatever order the fields are declared in.
+        let normalised = serde_json::to_value(request)
+            .and_then(|value| serde_json::to_vec(&value))
+            .context("Failed to normalise the request to fingerprint it")?;
+
+        Ok(Self(hex::encode(Sha256::digest(normalised))))
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::RequestFingerprint;
+
+    #[test]
+    fn field_order_does_not_change_the_fingerprint() {
+        let a = RequestFingerprint::of(&json!({"title": "Hello", "body": "World"})).unwrap();
+        let b = RequestFingerprint::of(&json!({"body": "World", "title": "Hello"})).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn different_payloads_have_different_fingerprints() {
+        let a = RequestFingerprint::of(&json!({"title": "Hello"})).unwrap();
+        let b = RequestFingerprint::of(&json!({"title": "Hello!"})).unwrap();
+        assert_ne!(a, b);
+    }
+}
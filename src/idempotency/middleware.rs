@@ -0,0 +1,250 @@
+//! src/idempotency/middleware.rs
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::{
+    save_response, try_processing, IdempotencyError, IdempotencyKey, IdempotencyPolicy,
+    IdempotencyScope, NextAction, RequestFingerprint,
+};
+use crate::authentication::{ApiUser, UserId};
+use crate::client_ip::client_ip;
+use crate::error::Problem;
+use crate::utils::{error_500, read_body, see_other};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Where forms carry their key, as browsers cannot set headers.
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+/// The transaction that claimed the idempotency key of the request.
+///
+/// What handlers write through it is committed together with their response, so that
+/// a request is never processed without its response being saved for the retries.
+/// Requests that come without a key, where the policy allows it, get a transaction too:
+/// it is committed when the handler succeeds, without anything being saved.
+#[derive(Clone)]
+pub struct IdempotentTransaction(Rc<Mutex<Transaction<'static, Postgres>>>);
+
+impl IdempotentTransaction {
+    pub async fn lock(&self) -> MutexGuard<'_, Transaction<'static, Postgres>> {
+        self.0.lock().await
+    }
+
+    fn into_inner(self) -> Result<Transaction<'static, Postgres>, actix_web::Error> {
+        Rc::try_unwrap(self.0)
+            .map(Mutex::into_inner)
+            .map_err(|_| error_500("The handler held on to the idempotent transaction"))
+    }
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Self>()
+                .cloned()
+                .ok_or_else(|| error_500("The route does not enforce idempotency")),
+        )
+    }
+}
+
+/// Process each idempotency key once: requests reusing it get the response saved for
+/// the first one back instead of being processed again.
+///
+/// The key is read from the `Idempotency-Key` header, or else from the `idempotency_key`
+/// field of the body. Only successful responses are saved: after an error the same key
+/// can be used to try again. Forms that redirect back with an error attach a [`Problem`]
+/// to the redirect, which marks it as an error as well.
+pub async fn enforce_idempotency(
+    policy: IdempotencyPolicy,
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let pool = req
+        .app_data::<Data<PgPool>>()
+        .expect("The database pool must be registered as application data")
+        .clone();
+    let body = read_body(&mut req).await?;
+    let idempotency_key = match read_key(&req, body.as_ref()) {
+        Ok(Some(idempotency_key)) => idempotency_key,
+        Ok(None) if !policy.key_required => return run_untracked(&pool, req, next).await,
+        Ok(None) => return Ok(reject(&policy, req, IdempotencyError::MissingKey)),
+        Err(error) => return Ok(reject(&policy, req, error)),
+    };
+    // The handler will reject what it cannot parse either.
+    let Some(body) = body else {
+        return run_untracked(&pool, req, next).await;
+    };
+
+    let fingerprint = fingerprint(body, &policy).map_err(error_500)?;
+    let scope = requesting_scope(&mut req).await;
+
+    let next_action =
+        match try_processing(&pool, &idempotency_key, scope, &fingerprint, &policy).await {
+            Ok(next_action) => next_action,
+            Err(error) => return Ok(reject(&policy, req, error)),
+        };
+    let transaction = match next_action {
+        NextAction::StartProcessing(transaction) => {
+            IdempotentTransaction(Rc::new(Mutex::new(*transaction)))
+        }
+        NextAction::ReturnSavedResponse(saved_response) => {
+            if let Some(message) = policy.replayed_message {
+                FlashMessage::info(message).send();
+            }
+            return Ok(req.into_response(saved_response));
+        }
+    };
+
+    let (request, response) = call_in_transaction(&transaction, req, next).await?;
+    // Dropping the transaction rolls back whatever the handler did.
+    if is_failure(&response) {
+        return Ok(ServiceResponse::new(request, response));
+    }
+
+    let response = save_response(transaction.into_inner()?, &idempotency_key, scope, response)
+        .await
+        .map_err(error_500)?;
+
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// Run a request that has no key to track in a transaction of its own.
+async fn run_untracked(
+    pool: &PgPool,
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(error_500)?;
+    let transaction = IdempotentTransaction(Rc::new(Mutex::new(transaction)));
+
+    let (request, response) = call_in_transaction(&transaction, req, next).await?;
+    if !is_failure(&response) {
+        transaction
+            .into_inner()?
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction.")
+            .map_err(error_500)?;
+    }
+
+    Ok(ServiceResponse::new(request, response))
+}
+
+async fn call_in_transaction(
+    transaction: &IdempotentTransaction,
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<(HttpRequest, HttpResponse), actix_web::Error> {
+    req.extensions_mut().insert(transaction.clone());
+    let (request, response) = next.call(req).await?.map_into_boxed_body().into_parts();
+    request.extensions_mut().remove::<IdempotentTransaction>();
+    Ok((request, response))
+}
+
+fn is_failure(response: &HttpResponse) -> bool {
+    let status = response.status();
+    status.is_client_error()
+        || status.is_server_error()
+        || response.extensions().get::<Problem>().is_some()
+}
+
+fn read_key(
+    req: &ServiceRequest,
+    body: Option<&Value>,
+) -> Result<Option<IdempotencyKey>, IdempotencyError> {
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value
+            .to_str()
+            .context("The idempotency key is not valid UTF-8")
+            .map_err(IdempotencyError::InvalidKey)?
+            .to_owned(),
+        None => match body.and_then(|body| body.get(IDEMPOTENCY_KEY_FIELD)?.as_str()) {
+            Some(idempotency_key) => idempotency_key.to_owned(),
+            None => return Ok(None),
+        },
+    };
+
+    IdempotencyKey::try_from(idempotency_key)
+        .map(Some)
+        .map_err(IdempotencyError::InvalidKey)
+}
+
+/// Fingerprint what the body asks for, leaving out the key itself.
+fn fingerprint(
+    mut body: Value,
+    policy: &IdempotencyPolicy,
+) -> Result<RequestFingerprint, anyhow::Error> {
+    if let Value::Object(fields) = &mut body {
+        fields.remove(IDEMPOTENCY_KEY_FIELD);
+        for field in policy.unfingerprinted_fields {
+            fields.remove(*field);
+        }
+    }
+
+    RequestFingerprint::of(&body)
+}
+
+/// The user the request is made on behalf of, through their session or an API token,
+/// or else the client that sent it.
+async fn requesting_scope(req: &mut ServiceRequest) -> IdempotencyScope {
+    if let Some(user_id) = req.extensions().get::<UserId>() {
+        return IdempotencyScope::User(**user_id);
+    }
+
+    match req.extract::<Option<ApiUser>>().await.ok().flatten() {
+        Some(api_user) => {
+            let user_id = *api_user.user_id;
+            // Kept for the handler, so that it does not authenticate the token again.
+            req.extensions_mut().insert(api_user);
+            IdempotencyScope::User(user_id)
+        }
+        None => IdempotencyScope::client(client_ip(req.request())),
+    }
+}
+
+/// Answer form submissions like the form's own errors, and anything else with a problem.
+fn reject(
+    policy: &IdempotencyPolicy,
+    req: ServiceRequest,
+    error: IdempotencyError,
+) -> ServiceResponse<BoxBody> {
+    let message = match &error {
+        IdempotencyError::KeyReused => {
+            "This form has already been submitted with different values. Please try again."
+        }
+        IdempotencyError::RequestInProgress { .. } => {
+            "Your previous submission is still being processed. Please check again in a moment."
+        }
+        _ => return req.error_response(error),
+    };
+    let Some(location) = policy.form_location else {
+        return req.error_response(error);
+    };
+
+    FlashMessage::error(message).send();
+    let response = Problem::new(error.status_code())
+        .with_detail(error.to_string())
+        .attach_to(see_other(location));
+    req.error_response(InternalError::from_response(error, response))
+}
@@ -1,5 +1,13 @@
+mod cleanup;
+mod fingerprint;
 mod key;
+mod middleware;
 mod persistence;
+mod policy;
 
+pub use cleanup::*;
+pub use fingerprint::*;
 pub use key::*;
+pub use middleware::*;
 pub use persistence::*;
+pub use policy::*;
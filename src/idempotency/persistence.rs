@@ -1,8 +1,20 @@
-use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse, ResponseError};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use tokio::time::Instant;
 use uuid::Uuid;
 
-use super::IdempotencyKey;
+use super::{IdempotencyKey, IdempotencyPolicy, RequestFingerprint};
+use crate::error::Problem;
+use crate::routes::error_chain_fmt;
+
+/// How often a request checks whether a concurrent request with its key has finished.
+const IN_PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -11,15 +23,98 @@ struct HeaderPairRecord {
     value: Vec<u8>,
 }
 
+/// Whose requests an idempotency key belongs to: a saved response is only replayed
+/// within the scope it was saved for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdempotencyScope {
+    /// The user making the request, through their session or an API token.
+    User(Uuid),
+    /// Anonymous requests are scoped to the address of the client that sent them.
+    Client(Uuid),
+}
+
+impl IdempotencyScope {
+    /// The scope of anonymous requests from `ip`, stored as a hash rather than the address.
+    pub fn client(ip: Option<IpAddr>) -> Self {
+        let ip = ip.map_or("unknown".into(), |ip| ip.to_string());
+        let digest = Sha256::digest(format!("idempotency-client:{}", ip).as_bytes());
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        Self::Client(Uuid::from_bytes(bytes))
+    }
+
+    fn id(&self) -> Uuid {
+        match self {
+            Self::User(id) | Self::Client(id) => *id,
+        }
+    }
+
+    fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Self::User(user_id) => Some(*user_id),
+            Self::Client(_) => None,
+        }
+    }
+}
+
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    StartProcessing(Box<Transaction<'static, Postgres>>),
     ReturnSavedResponse(HttpResponse),
 }
 
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("An `Idempotency-Key` header or `idempotency_key` field is required.")]
+    MissingKey,
+    #[error("The idempotency key is not valid: {0}")]
+    InvalidKey(#[source] anyhow::Error),
+    #[error("This idempotency key has already been used for a different request.")]
+    KeyReused,
+    #[error("A request with this idempotency key is still being processed.")]
+    RequestInProgress { retry_after: Duration },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IdempotencyError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingKey | Self::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            Self::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::RequestInProgress { .. } => StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            Self::MissingKey | Self::InvalidKey(_) | Self::KeyReused => {
+                problem.with_detail(self.to_string()).into_response()
+            }
+            Self::RequestInProgress { retry_after } => {
+                let mut response = problem.with_detail(self.to_string()).into_response();
+                response.headers_mut().insert(
+                    RETRY_AFTER,
+                    retry_after.as_secs().max(1).to_string().parse().unwrap(),
+                );
+                response
+            }
+            Self::UnexpectedError(_) => problem.into_response(),
+        }
+    }
+}
+
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: IdempotencyScope,
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
@@ -28,10 +123,10 @@ pub async fn get_saved_response(
             response_headers as "response_headers!: Vec<HeaderPairRecord>",
             response_body as "response_body!"
           FROM idempotency
-          WHERE user_id = $1
+          WHERE scope = $1
           AND idempotency_key = $2
         "#,
-        user_id,
+        scope.id(),
         idempotency_key.as_ref()
     )
     .fetch_optional(pool)
@@ -57,7 +152,7 @@ pub async fn get_saved_response(
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: IdempotencyScope,
     http_response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
@@ -89,10 +184,10 @@ pub async fn save_response(
                 response_status_code = $3,
                 response_headers = $4,
                 response_body = $5
-            WHERE user_id = $1
+            WHERE scope = $1
             AND idempotency_key = $2
         "#,
-        user_id,
+        scope.id(),
         idempotency_key.as_ref(),
         status_code,
         headers,
@@ -110,36 +205,139 @@ pub async fn save_response(
     Ok(http_response)
 }
 
+/// Claim `idempotency_key` for a new request, or fetch the response of the request
+/// that already used it.
+///
+/// Keys are only remembered for the retention of `policy`: a record older than that is
+/// reset and the request is processed as if the key had never been seen. Reusing a key
+/// that is still remembered for a request with another `fingerprint` is an error, as
+/// the response saved for it would not answer the new request.
+///
+/// While another request with the same key is being processed, this polls for its
+/// response for as long as `policy` allows before giving up.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
+    scope: IdempotencyScope,
+    fingerprint: &RequestFingerprint,
+    policy: &IdempotencyPolicy,
+) -> Result<NextAction, IdempotencyError> {
+    let deadline = Instant::now() + policy.max_wait;
+
+    loop {
+        if let Some(next_action) =
+            try_claim(pool, idempotency_key, scope, fingerprint, policy.retention).await?
+        {
+            return Ok(next_action);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(IdempotencyError::RequestInProgress {
+                retry_after: policy.retry_after,
+            });
+        }
+        tokio::time::sleep(IN_PROGRESS_POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// One attempt at `try_processing`, returning `None` if another request with the same
+/// key is still being processed.
+///
+/// Requests hold an advisory lock on their key until their response is saved, so that
+/// concurrent ones can tell they are in progress instead of blocking on their row.
+async fn try_claim(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    scope: IdempotencyScope,
+    fingerprint: &RequestFingerprint,
+    retention: Duration,
+) -> Result<Option<NextAction>, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to start a transaction")?;
+
+    let lock_acquired = sqlx::query_scalar!(
+        r#"SELECT pg_try_advisory_xact_lock(hashtextextended($1, 0)) AS "acquired!""#,
+        format!("idempotency:{}:{}", scope.id(), idempotency_key.as_ref())
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to lock the idempotency key")?;
+    if !lock_acquired {
+        return Ok(None);
+    }
 
     let query = sqlx::query!(
         r#"
             INSERT INTO idempotency (
                 user_id,
+                scope,
                 idempotency_key,
+                request_fingerprint,
                 created_at
             )
-            VALUES ($1, $2, now())
-            ON CONFLICT DO NOTHING
+            VALUES ($1, $2, $3, $4, now())
+            ON CONFLICT (scope, idempotency_key) DO UPDATE
+            SET
+                request_fingerprint = $4,
+                created_at = now(),
+                response_status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL
+            WHERE idempotency.created_at < now() - make_interval(secs => $5)
         "#,
-        user_id,
-        idempotency_key.as_ref()
+        scope.user_id(),
+        scope.id(),
+        idempotency_key.as_ref(),
+        fingerprint.as_ref(),
+        retention.as_secs_f64()
     );
 
-    let n_inserted_rows = transaction.execute(query).await?.rows_affected();
+    let n_inserted_rows = transaction
+        .execute(query)
+        .await
+        .context("Failed to claim the idempotency key")?
+        .rows_affected();
 
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+        return Ok(Some(NextAction::StartProcessing(Box::new(transaction))));
+    }
 
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+    if get_saved_fingerprint(pool, idempotency_key, scope)
+        .await?
+        .is_some_and(|saved| saved != fingerprint.as_ref())
+    {
+        return Err(IdempotencyError::KeyReused);
     }
+
+    let saved_response = get_saved_response(pool, idempotency_key, scope)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+
+    Ok(Some(NextAction::ReturnSavedResponse(saved_response)))
+}
+
+/// The fingerprint of the request that used `idempotency_key`, if it was recorded.
+async fn get_saved_fingerprint(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    scope: IdempotencyScope,
+) -> Result<Option<String>, anyhow::Error> {
+    let record = sqlx::query!(
+        r#"
+          SELECT request_fingerprint
+          FROM idempotency
+          WHERE scope = $1
+          AND idempotency_key = $2
+        "#,
+        scope.id(),
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the fingerprint of the saved request")?;
+
+    Ok(record.and_then(|record| record.request_fingerprint))
 }
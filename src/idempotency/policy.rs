@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use crate::configuration::{ConcurrentRequestHandling, IdempotencySettings};
+
+/// How one endpoint handles idempotency keys.
+#[derive(Debug, Clone, Copy)]
+pub struct IdempotencyPolicy {
+    pub(super) retention: Duration,
+    pub(super) max_wait: Duration,
+    pub(super) retry_after: Duration,
+    pub(super) key_required: bool,
+    pub(super) form_location: Option<&'static str>,
+    pub(super) replayed_message: Option<&'static str>,
+    pub(super) unfingerprinted_fields: &'static [&'static str],
+}
+
+impl IdempotencyPolicy {
+    pub fn new(
+        settings: &IdempotencySettings,
+        concurrent_requests: ConcurrentRequestHandling,
+    ) -> Self {
+        Self {
+            retention: settings.retention(),
+            max_wait: concurrent_requests.max_wait(),
+            retry_after: concurrent_requests.retry_after(),
+            key_required: true,
+            form_location: None,
+            replayed_message: None,
+            unfingerprinted_fields: &[],
+        }
+    }
+
+    /// Process requests without an idempotency key as usual, for clients that
+    /// predate them.
+    pub fn optional(mut self) -> Self {
+        self.key_required = false;
+        self
+    }
+
+    /// Answer rejected submissions like the form's own errors, with a flash message
+    /// explaining why and a redirect back to `location`.
+    pub fn for_form(mut self, location: &'static str) -> Self {
+        self.form_location = Some(location);
+        self
+    }
+
+    /// Flash `message` again with the response saved for a form submission, as flash
+    /// messages are not saved with it. Only use this for forms that save nothing but
+    /// their successful submissions.
+    pub fn replaying(mut self, message: &'static str) -> Self {
+        self.replayed_message = Some(message);
+        self
+    }
+
+    /// Leave `fields` out of the fingerprint stored with the key, e.g. passwords, that
+    /// must not be stored in any form. Reusing the key with other values for them
+    /// counts as a retry.
+    pub fn without_fingerprinting(mut self, fields: &'static [&'static str]) -> Self {
+        self.unfingerprinted_fields = fields;
+        self
+    }
+}
@@ -23,7 +23,7 @@ pub enum ExecutionOutcome {
 }
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = Application::db_connection_pool(&configuration.database)?;
+    let connection_pool = Application::db_connection_pool(&configuration.database);
 
     let email_client = configuration.email_client.client();
 
@@ -1,13 +1,18 @@
 //! src/lib.rs
 
 pub mod authentication;
+pub mod client_ip;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod error;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mail_domain;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
 pub mod telemetry;
+pub mod templates;
 pub mod utils;
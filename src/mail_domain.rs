@@ -0,0 +1,272 @@
+//! src/mail_domain.rs
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+
+/// Answers whether a domain can receive email.
+///
+/// Abstracted behind a trait so that tests can swap DNS for an in-process fake.
+#[async_trait::async_trait]
+pub trait MailDomainResolver: Send + Sync {
+    /// `Ok(false)` means DNS positively answered that the domain has neither MX
+    /// nor A/AAAA records; lookup failures are reported as errors.
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+/// Resolves domains against the DNS servers configured on the host.
+pub struct DnsMailDomainResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMailDomainResolver {
+    pub fn from_system_conf() -> Result<Self, anyhow::Error> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to read the system DNS configuration")?;
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait::async_trait]
+impl MailDomainResolver for DnsMailDomainResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // The trailing dot stops the resolver from trying the host's search domains.
+        let fqdn = format!("{}.", domain.trim_end_matches('.'));
+
+        match self.resolver.mx_lookup(fqdn.as_str()).await {
+            // A "null MX" (RFC 7505) explicitly declares that the domain takes no mail.
+            Ok(lookup) => return Ok(lookup.iter().any(|mx| !mx.exchange().is_root())),
+            Err(error) if is_no_records(&error) => {}
+            Err(error) => return Err(error).context("Failed to look up MX records"),
+        }
+
+        // Without MX records mail is delivered to the domain's address records.
+        match self.resolver.lookup_ip(fqdn.as_str()).await {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(error) if is_no_records(&error) => Ok(false),
+            Err(error) => Err(error).context("Failed to look up address records"),
+        }
+    }
+}
+
+fn is_no_records(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+/// Caches the answers of a `MailDomainResolver` and bounds how long a lookup may take.
+pub struct MailDomainCheck {
+    resolver: Arc<dyn MailDomainResolver>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache_max_entries: usize,
+    cache: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl MailDomainCheck {
+    pub fn new(
+        resolver: Arc<dyn MailDomainResolver>,
+        timeout: Duration,
+        cache_ttl: Duration,
+        cache_max_entries: usize,
+    ) -> Self {
+        Self {
+            resolver,
+            timeout,
+            cache_ttl,
+            cache_max_entries,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `domain` can receive email.
+    ///
+    /// Lookups that fail or time out are treated as a yes: a flaky DNS server must
+    /// not lock legitimate subscribers out. Only definitive answers are cached.
+    #[tracing::instrument(name = "Checking the mail records of a domain", skip(self))]
+    pub async fn accepts_mail(&self, domain: &str) -> bool {
+        if let Some(accepts_mail) = self.cached(domain) {
+            return accepts_mail;
+        }
+
+        let accepts_mail =
+            match tokio::time::timeout(self.timeout, self.resolver.accepts_mail(domain)).await {
+                Ok(Ok(accepts_mail)) => accepts_mail,
+                Ok(Err(error)) => {
+                    tracing::warn!(
+                        error.cause_chain = ?error,
+                        error.message = %error,
+                        "Failed to look up the mail records of a domain, accepting it"
+                    );
+                    return true;
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        "Looking up the mail records of a domain timed out, accepting it"
+                    );
+                    return true;
+                }
+            };
+
+        self.remember(domain, accepts_mail);
+
+        accepts_mail
+    }
+
+    /// Cache an answer, making room for it once the cache is full: expired answers go
+    /// first, then the ones closest to expiring.
+    fn remember(&self, domain: &str, accepts_mail: bool) {
+        if self.cache_max_entries == 0 {
+            return;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.cache_max_entries && !cache.contains_key(domain) {
+            let now = Instant::now();
+            cache.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        while cache.len() >= self.cache_max_entries && !cache.contains_key(domain) {
+            let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (_, expires_at))| *expires_at)
+                .map(|(domain, _)| domain.clone())
+            else {
+                break;
+            };
+            cache.remove(&oldest);
+        }
+
+        cache.insert(
+            domain.to_owned(),
+            (accepts_mail, Instant::now() + self.cache_ttl),
+        );
+    }
+
+    fn cached(&self, domain: &str) -> Option<bool> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(domain) {
+            Some((accepts_mail, expires_at)) if *expires_at > Instant::now() => Some(*accepts_mail),
+            Some(_) => {
+                cache.remove(domain);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{MailDomainCheck, MailDomainResolver};
+
+    /// Accepts every domain except `dead.example`, counting the lookups it serves.
+    #[derive(Default)]
+    struct CountingResolver {
+        lookups: AtomicUsize,
+        delay: Duration,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl MailDomainResolver for CountingResolver {
+        async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                anyhow::bail!("SERVFAIL");
+            }
+            Ok(domain != "dead.example")
+        }
+    }
+
+    fn check(resolver: &Arc<CountingResolver>) -> MailDomainCheck {
+        MailDomainCheck::new(
+            resolver.clone(),
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            100,
+        )
+    }
+
+    #[tokio::test]
+    async fn answers_are_cached() {
+        let resolver = Arc::new(CountingResolver::default());
+        let check = check(&resolver);
+
+        assert!(check.accepts_mail("alive.example").await);
+        assert!(check.accepts_mail("alive.example").await);
+        assert!(!check.accepts_mail("dead.example").await);
+        assert!(!check.accepts_mail("dead.example").await);
+
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn the_cache_never_exceeds_its_size() {
+        let resolver = Arc::new(CountingResolver::default());
+        let check = MailDomainCheck::new(
+            resolver.clone(),
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            2,
+        );
+
+        for domain in ["a.example", "b.example", "c.example"] {
+            assert!(check.accepts_mail(domain).await);
+        }
+        assert_eq!(check.cache.lock().unwrap().len(), 2);
+
+        // The oldest answer made room for the newest one.
+        assert!(check.accepts_mail("c.example").await);
+        assert!(check.accepts_mail("a.example").await);
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn expired_answers_are_evicted_first() {
+        let resolver = Arc::new(CountingResolver::default());
+        let check = MailDomainCheck::new(
+            resolver.clone(),
+            Duration::from_millis(100),
+            Duration::ZERO,
+            2,
+        );
+
+        for domain in ["a.example", "b.example", "c.example"] {
+            assert!(check.accepts_mail(domain).await);
+        }
+
+        assert_eq!(check.cache.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_lookups_are_accepted_and_not_cached() {
+        let resolver = Arc::new(CountingResolver {
+            delay: Duration::from_secs(10),
+            ..CountingResolver::default()
+        });
+        let check = check(&resolver);
+
+        assert!(check.accepts_mail("dead.example").await);
+        assert!(check.accepts_mail("dead.example").await);
+
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_are_accepted() {
+        let resolver = Arc::new(CountingResolver {
+            fail: true,
+            ..CountingResolver::default()
+        });
+
+        assert!(check(&resolver).accepts_mail("dead.example").await);
+    }
+}
@@ -2,28 +2,41 @@ use std::fmt::{Debug, Display};
 
 use tokio::task::JoinError;
 use zero_to_prod::configuration::Configuration;
+use zero_to_prod::idempotency::run_cleanup_until_stopped;
 use zero_to_prod::issue_delivery_worker::run_worker_until_stopped;
 use zero_to_prod::startup::Application;
 use zero_to_prod::telemetry::Telemetry;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = Configuration::get().expect("Failed to read configuration.");
+    let config = match Configuration::get() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Failed to read configuration: {error}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(problems) = config.validate() {
+        eprintln!("{problems}");
+        std::process::exit(1);
+    }
 
     Telemetry::init_subscriber(&config.application.name, "info".into(), std::io::stdout);
 
-    let connection_pool =
-        Application::db_connection_pool(&config.database).expect("Failed to connect to Postgres.");
+    let connection_pool = Application::db_connection_pool(&config.database);
 
     let application = Application::build(config.clone(), connection_pool).await?;
-    let worker = run_worker_until_stopped(config);
+    let worker = run_worker_until_stopped(config.clone());
+    let idempotency_cleanup = run_cleanup_until_stopped(config);
 
     let application_task = tokio::spawn(application.run_until_stopped());
     let worker_task = tokio::spawn(worker);
+    let idempotency_cleanup_task = tokio::spawn(idempotency_cleanup);
 
     tokio::select! {
         outcome = application_task => report_exit("API", outcome),
         outcome = worker_task => report_exit("Background worker", outcome),
+        outcome = idempotency_cleanup_task => report_exit("Idempotency cleanup", outcome),
     }
 
     Ok(())
@@ -0,0 +1,146 @@
+//! src/rate_limit/middleware.rs
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+
+use super::RateLimiter;
+use crate::client_ip::client_ip;
+use crate::configuration::{Quota, RouteRateLimit};
+use crate::error::Problem;
+use crate::session_state::TypedSession;
+use crate::utils::{error_500, read_body_field};
+
+/// The limits applied to a single route.
+#[derive(Clone)]
+pub struct RateLimitPolicy {
+    scope: &'static str,
+    identity: Option<Identity>,
+    limits: RouteRateLimit,
+}
+
+/// What the per-identity quota is counted against.
+#[derive(Clone, Copy)]
+enum Identity {
+    /// A field of the form or JSON body.
+    Field(&'static str),
+    /// The user whose password was accepted but who still owes a second factor.
+    PendingUser,
+}
+
+impl RateLimitPolicy {
+    pub fn new(scope: &'static str, limits: RouteRateLimit) -> Self {
+        Self {
+            scope,
+            identity: None,
+            limits,
+        }
+    }
+
+    /// Also count requests per value of `field` in the form or JSON body,
+    /// e.g. per email address rather than only per client IP.
+    pub fn keyed_on(mut self, field: &'static str) -> Self {
+        self.identity = Some(Identity::Field(field));
+        self
+    }
+
+    /// Also count requests per user halfway through logging in, however many
+    /// clients they are spread across.
+    pub fn keyed_on_pending_user(mut self) -> Self {
+        self.identity = Some(Identity::PendingUser);
+        self
+    }
+}
+
+/// Reject requests that exceed the policy's quotas with a `429 Too Many Requests`.
+///
+/// If the counters cannot be updated the request is let through: an unavailable
+/// Redis must not take the subscription and login pages down with it.
+pub async fn enforce_rate_limit(
+    policy: RateLimitPolicy,
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limiter = match req.app_data::<Data<RateLimiter>>() {
+        Some(limiter) => limiter.clone(),
+        None => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+
+    let mut checks: Vec<(String, Quota)> = Vec::new();
+
+    if let Some(quota) = policy.limits.per_ip {
+        let ip = client_ip(req.request()).map_or("unknown".into(), |ip| ip.to_string());
+        checks.push((format!("rate_limit:{}:ip:{}", policy.scope, ip), quota));
+    }
+
+    if let (Some(identity), Some(quota)) = (policy.identity, policy.limits.per_identity) {
+        let identity = match identity {
+            Identity::Field(field) => read_identity(&mut req, field)
+                .await?
+                .map(|value| format!("{}:{}", field, value)),
+            Identity::PendingUser => req
+                .extract::<TypedSession>()
+                .await?
+                .get_pending_user_id()
+                .map_err(error_500)?
+                .map(|user_id| format!("user:{}", user_id)),
+        };
+        if let Some(identity) = identity {
+            checks.push((format!("rate_limit:{}:{}", policy.scope, identity), quota));
+        }
+    }
+
+    for (key, quota) in checks {
+        match limiter.hit(&key, quota.window()).await {
+            Ok(usage) if usage.hits > quota.max_requests => {
+                tracing::warn!(rate_limit.key = %key, "Rate limit exceeded");
+                let response = Problem::new(StatusCode::TOO_MANY_REQUESTS)
+                    .with_detail("Too many requests, please try again later.")
+                    .into_response();
+                let mut response = req.into_response(response);
+                response.headers_mut().insert(
+                    RETRY_AFTER,
+                    usage
+                        .resets_in
+                        .as_secs()
+                        .max(1)
+                        .to_string()
+                        .parse()
+                        .unwrap(),
+                );
+                return Ok(response.map_into_right_body());
+            }
+            Ok(_) => {}
+            Err(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to check rate limit, letting the request through"
+                );
+            }
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Read `field` from a form or JSON body, normalised to compare equal however it was typed.
+async fn read_identity(
+    req: &mut ServiceRequest,
+    field: &str,
+) -> Result<Option<String>, actix_web::Error> {
+    Ok(read_body_field(req, field)
+        .await?
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty()))
+}
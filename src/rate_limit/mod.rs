@@ -0,0 +1,7 @@
+//! src/rate_limit/mod.rs
+
+mod middleware;
+mod store;
+
+pub use middleware::*;
+pub use store::*;
@@ -0,0 +1,154 @@
+//! src/rate_limit/store.rs
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use redis::aio::MultiplexedConnection;
+
+/// The state of a fixed window counter after registering a hit.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowUsage {
+    pub hits: u64,
+    pub resets_in: Duration,
+}
+
+/// Where rate limit counters are kept.
+///
+/// Redis is shared by every instance of the application, the in-memory store
+/// is meant for tests and single-instance development setups.
+pub enum RateLimiter {
+    Redis(RedisRateLimitStore),
+    InMemory(InMemoryRateLimitStore),
+}
+
+impl RateLimiter {
+    pub fn redis(redis_uri: &str) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri).context("Invalid Redis URI")?;
+        Ok(Self::Redis(RedisRateLimitStore {
+            client,
+            connection: tokio::sync::Mutex::new(None),
+        }))
+    }
+
+    pub fn in_memory() -> Self {
+        Self::InMemory(InMemoryRateLimitStore::default())
+    }
+
+    /// Count a hit against `key` in a window of `window` length.
+    pub async fn hit(&self, key: &str, window: Duration) -> Result<WindowUsage, anyhow::Error> {
+        match self {
+            Self::Redis(store) => store.hit(key, window).await,
+            Self::InMemory(store) => Ok(store.hit(key, window)),
+        }
+    }
+}
+
+pub struct RedisRateLimitStore {
+    client: redis::Client,
+    connection: tokio::sync::Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisRateLimitStore {
+    async fn connection(&self) -> Result<MultiplexedConnection, anyhow::Error> {
+        let mut connection = self.connection.lock().await;
+
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+
+        let new_connection = self
+            .client
+            .get_multiplexed_tokio_connection()
+            .await
+            .context("Failed to connect to Redis")?;
+        *connection = Some(new_connection.clone());
+
+        Ok(new_connection)
+    }
+
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowUsage, anyhow::Error> {
+        let mut connection = self.connection().await?;
+        let window_seconds = window.as_secs().max(1);
+
+        // The first hit of a window creates the counter with its expiry,
+        // subsequent hits only increment it.
+        let result: Result<(u64, i64), redis::RedisError> = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(key)
+            .arg(0)
+            .arg("EX")
+            .arg(window_seconds)
+            .arg("NX")
+            .ignore()
+            .incr(key, 1)
+            .ttl(key)
+            .query_async(&mut connection)
+            .await;
+
+        match result {
+            Ok((hits, ttl)) => Ok(WindowUsage {
+                hits,
+                resets_in: Duration::from_secs(ttl.max(1) as u64),
+            }),
+            Err(error) => {
+                // Drop the connection so that the next hit reconnects.
+                self.connection.lock().await.take();
+                Err(error).context("Failed to update a rate limit counter in Redis")
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryRateLimitStore {
+    windows: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl InMemoryRateLimitStore {
+    fn hit(&self, key: &str, window: Duration) -> WindowUsage {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        // Forget the windows that are over, as Redis does with expired keys.
+        windows.retain(|_, (_, resets_at)| *resets_at > now);
+        let (hits, resets_at) = windows.entry(key.to_owned()).or_insert((0, now + window));
+        *hits += 1;
+
+        WindowUsage {
+            hits: *hits,
+            resets_in: resets_at.saturating_duration_since(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::InMemoryRateLimitStore;
+
+    #[test]
+    fn hits_are_counted_per_key_within_a_window() {
+        let store = InMemoryRateLimitStore::default();
+        let window = Duration::from_secs(60);
+
+        store.hit("a", window);
+        assert_eq!(store.hit("a", window).hits, 2);
+        assert_eq!(store.hit("b", window).hits, 1);
+    }
+
+    #[test]
+    fn windows_that_are_over_are_forgotten() {
+        let store = InMemoryRateLimitStore::default();
+
+        store.hit("a", Duration::ZERO);
+        store.hit("b", Duration::ZERO);
+        let usage = store.hit("a", Duration::from_secs(60));
+
+        assert_eq!(usage.hits, 1);
+        assert_eq!(store.windows.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+//! src/routes/admin/api_tokens.rs
+
+use actix_web::web::{Data, Form, ReqData};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{create_api_token, get_api_tokens, revoke_api_token, ApiScope, UserId};
+use crate::templates::{read_flash_messages, render, AdminNav, ApiTokenCreatedPage, ApiTokensPage};
+use crate::utils::{error_500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RevokeApiTokenFormData {
+    token_id: Uuid,
+}
+
+pub async fn api_tokens_form(
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tokens = get_api_tokens(**user_id, &pool).await.map_err(error_500)?;
+    let scopes = ApiScope::ALL
+        .into_iter()
+        .filter(|scope| nav.role().allows(scope.required_role()))
+        .collect();
+
+    render(&ApiTokensPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        tokens,
+        scopes,
+    })
+    .map_err(error_500)
+}
+
+/// Create a token and show it once: only its hash is stored.
+///
+/// The form is read as pairs because each checked scope is sent as its own `scope` field.
+#[tracing::instrument(name = "Create an API token", skip(form, pool, nav))]
+pub async fn create_token(
+    form: Form<Vec<(String, String)>>,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (field, value) in form.into_inner() {
+        match field.as_str() {
+            "name" => name = value.trim().to_owned(),
+            "scope" => match ApiScope::try_from(value) {
+                Ok(scope) if !nav.role().allows(scope.required_role()) => {
+                    FlashMessage::error(format!(
+                        "Your role does not allow granting the {scope} scope."
+                    ))
+                    .send();
+                    return Ok(see_other("/admin/api-tokens"));
+                }
+                Ok(scope) if !scopes.contains(&scope) => scopes.push(scope),
+                Ok(_) => {}
+                Err(reason) => {
+                    FlashMessage::error(reason).send();
+                    return Ok(see_other("/admin/api-tokens"));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    if name.is_empty() {
+        FlashMessage::error("Please give the token a name.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("Please choose at least one scope.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+
+    let token = create_api_token(**user_id, &name, &scopes, &pool)
+        .await
+        .map_err(error_500)?;
+
+    render(&ApiTokenCreatedPage {
+        nav,
+        messages: Vec::new(),
+        name,
+        token,
+    })
+    .map_err(error_500)
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(form, pool))]
+pub async fn revoke_token(
+    form: Form<RevokeApiTokenFormData>,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_api_token(**user_id, form.token_id, &pool)
+        .await
+        .map_err(error_500)?
+    {
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("That API token does not exist or has already been revoked.").send();
+    }
+
+    Ok(see_other("/admin/api-tokens"))
+}
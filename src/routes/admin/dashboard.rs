@@ -1,57 +1,55 @@
 //! src/routes/admin/dashboard.rs
-use actix_web::{
-    http::header::{ContentType, LOCATION},
-    web::Data,
-    HttpResponse,
-};
+use actix_web::{http::header::LOCATION, web::Data, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use std::fmt::Debug;
 use uuid::Uuid;
 
-use crate::{session_state::TypedSession, utils::error_500};
+use crate::{
+    authentication::get_recent_failed_login_attempts,
+    session_state::TypedSession,
+    templates::{read_flash_messages, render, AdminNav, DashboardPage},
+    utils::error_500,
+};
+
+/// How many failed login attempts the dashboard lists.
+const RECENT_FAILED_LOGINS: i64 = 10;
 
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: Data<PgPool>,
+    nav: AdminNav,
+    flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(error_500)? {
-        get_username(user_id, &pool).await.map_err(error_500)?
+        get_username(user_id, pool.get_ref())
+            .await
+            .map_err(error_500)?
     } else {
         return Ok(HttpResponse::SeeOther()
             .insert_header((LOCATION, "/login"))
             .finish());
     };
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-            </head>
-            <body>
-                <p>Welcome {username}!</p>
-                <p>Available actions:</p>
-                <ol>
-                    <li><a href="/admin/password">Change password</a></li>
-                    <li>
-                        <form name="logoutForm" action="/admin/logout" method="post">
-                            <input type="submit" value="Logout">
-                        </form>
-                    </li>
-                </ol>
-            </body>
-            </html>
-            "#,
-        )))
+    let failed_logins = get_recent_failed_login_attempts(RECENT_FAILED_LOGINS, &pool)
+        .await
+        .map_err(error_500)?;
+
+    render(&DashboardPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        username,
+        failed_logins,
+    })
+    .map_err(error_500)
 }
 
-#[tracing::instrument(name = "Get username", skip(pool))]
-pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+#[tracing::instrument(name = "Get username", skip(executor))]
+pub async fn get_username(
+    user_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<String, anyhow::Error> {
     let row = sqlx::query!(
         r#"
         SELECT username
@@ -60,7 +58,7 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
         "#,
         user_id,
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await
     .context("Failed to perform a query to retrieve a username.")?;
 
@@ -0,0 +1,180 @@
+//! src/routes/admin/invitations.rs
+
+use actix_web::web::{Data, Form, ReqData};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::templates::{read_flash_messages, render, AdminNav, InvitationsPage};
+use crate::utils::{error_500, generate_token, hash_token, see_other};
+
+/// How long an invitation link can be used for.
+pub const INVITATION_VALIDITY: TimeDelta = TimeDelta::days(7);
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    email: String,
+    role: Role,
+}
+
+pub struct PendingInvitation {
+    pub email: String,
+    pub role: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub async fn invitations_form(
+    pool: Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pending_invitations = get_pending_invitations(&pool).await.map_err(error_500)?;
+
+    render(&InvitationsPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        roles: Role::ALL,
+        pending_invitations,
+    })
+    .map_err(error_500)
+}
+
+#[tracing::instrument(
+    name = "Invite a new admin",
+    skip(form, pool, email_client, base_url, user_id),
+    fields(invitee_email = %form.email, invitee_role = %form.role, invited_by = %*user_id)
+)]
+pub async fn invite_user(
+    form: Form<InvitationFormData>,
+    pool: Data<PgPool>,
+    email_client: Data<EmailClient>,
+    base_url: Data<ApplicationBaseUrl>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InvitationFormData { email, role } = form.into_inner();
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(error) => {
+            FlashMessage::error(error).send();
+            return Ok(see_other("/admin/invitations"));
+        }
+    };
+
+    if email_is_taken(&pool, &email).await.map_err(error_500)? {
+        FlashMessage::error(format!("{} already has an account.", email)).send();
+        return Ok(see_other("/admin/invitations"));
+    }
+
+    let invitation_token = generate_token(32);
+    store_invitation(&pool, &invitation_token, &email, role, **user_id)
+        .await
+        .map_err(error_500)?;
+
+    send_invitation_email(&email_client, &email, &base_url.0, &invitation_token)
+        .await
+        .context("Failed to send an invitation email.")
+        .map_err(error_500)?;
+
+    FlashMessage::info(format!("An invitation has been sent to {}.", email)).send();
+
+    Ok(see_other("/admin/invitations"))
+}
+
+#[tracing::instrument(name = "Check whether an email belongs to a user", skip(pool))]
+async fn email_is_taken(pool: &PgPool, email: &SubscriberEmail) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE lower(email) = lower($1)) AS "taken!""#,
+        email.as_ref()
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up users by email.")?;
+
+    Ok(row.taken)
+}
+
+#[tracing::instrument(name = "Store an invitation", skip(pool, invitation_token))]
+async fn store_invitation(
+    pool: &PgPool,
+    invitation_token: &str,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations
+            (token_hash, email, role, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        hash_token(invitation_token),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        now,
+        now + INVITATION_VALIDITY
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an invitation in the database.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an invitation email",
+    skip(email_client, base_url, invitation_token)
+)]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    invitation_token: &str,
+) -> Result<(), reqwest::Error> {
+    let invitation_link = format!(
+        "{}/invitations/accept?invitation_token={}",
+        base_url, invitation_token
+    );
+
+    let html_email = format!(
+        "You have been invited to help run our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to create your account.",
+        invitation_link
+    );
+
+    let text_email = format!(
+        "You have been invited to help run our newsletter!\nVisit {} to create your account.",
+        invitation_link
+    );
+
+    email_client
+        .send_email(email, "You are invited!", &html_email, &text_email)
+        .await
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch pending invitations.")?;
+
+    Ok(invitations)
+}
@@ -1,14 +1,27 @@
 //! src/routes/admin/logout.rs
 
+use crate::authentication::{revoke_session, UserId};
 use crate::session_state::TypedSession;
 use crate::utils::{error_500, see_other};
+use actix_web::web::{Data, ReqData};
 use actix_web::HttpResponse;
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    session: TypedSession,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(error_500)?.is_none() {
         Ok(see_other("/login"))
     } else {
+        // Take the session off the sessions page as well.
+        if let Some(session_id) = session.get_session_id().map_err(error_500)? {
+            revoke_session(**user_id, session_id, &pool)
+                .await
+                .map_err(error_500)?;
+        }
         session.log_out();
         FlashMessage::info("You have successfully logged out.").send();
         Ok(see_other("/login"))
@@ -1,9 +1,17 @@
+mod api_tokens;
 mod dashboard;
+mod invitations;
 mod logout;
 mod newsletter;
 mod password;
+mod sessions;
+mod subscribers;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use dashboard::{admin_dashboard, get_username};
+pub use invitations::*;
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscribers::*;
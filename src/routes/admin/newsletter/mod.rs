@@ -2,4 +2,5 @@ mod get;
 mod post;
 
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub(crate) use post::{enqueue_delivery_tasks, insert_newsletter_issue};
+pub use post::{publish_newsletter, NEWSLETTER_PUBLISHED};
@@ -1,19 +1,21 @@
 use crate::authentication::UserId;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::utils::{error_400, error_500, see_other};
+use crate::idempotency::IdempotentTransaction;
+use crate::utils::{error_500, see_other};
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
+/// Flashed again when a resubmission of the form gets the saved response back.
+pub const NEWSLETTER_PUBLISHED: &str = "The newsletter issue has been published!";
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
 }
 
 #[tracing::instrument(
@@ -23,29 +25,15 @@ pub struct FormData {
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
-    pool: web::Data<PgPool>,
     user_id: ReqData<UserId>,
+    transaction: IdempotentTransaction,
 ) -> Result<HttpResponse, actix_web::Error> {
     let FormData {
         title,
         text_content,
         html_content,
-        idempotency_key,
     } = form.0;
-
-    let user_id = user_id.into_inner();
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(error_400)?;
-
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(error_500)?
-    {
-        NextAction::StartProcessing(db_transaction) => db_transaction,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            FlashMessage::info("The newsletter issue has been published!").send();
-            return Ok(saved_response);
-        }
-    };
+    let mut transaction = transaction.lock().await;
 
     let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
         .await
@@ -57,18 +45,13 @@ pub async fn publish_newsletter(
         .context("Failed to enqueue delivery tasks")
         .map_err(error_500)?;
 
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(error_500)?;
-
-    FlashMessage::info("The newsletter issue has been published!").send();
+    FlashMessage::info(NEWSLETTER_PUBLISHED).send();
 
-    Ok(response)
+    Ok(see_other("/admin/newsletters"))
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
@@ -98,7 +81,7 @@ async fn insert_newsletter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -0,0 +1,120 @@
+//! src/routes/admin/password/email.rs
+
+use actix_web::web::{Data, Form, ReqData};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use redact::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{
+    validate_credentials, AuthError, Credentials, PasswordHashing, UserId,
+};
+use crate::domain::SubscriberEmail;
+use crate::routes::admin::dashboard::get_username;
+use crate::templates::{read_flash_messages, render, AdminNav, EmailPage};
+use crate::utils::{error_500, see_other, violated_unique_constraint};
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    email: String,
+    current_password: Secret<String>,
+}
+
+pub async fn email_form(
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = get_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(error_500)?;
+
+    render(&EmailPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        email,
+    })
+    .map_err(error_500)
+}
+
+/// Set the address password reset links are sent to.
+///
+/// The current password is required: whoever could change the address could
+/// otherwise take the account over through a reset.
+#[tracing::instrument(name = "Change email address", skip(form, pool, hashing))]
+pub async fn change_email(
+    form: Form<EmailFormData>,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    hashing: Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let form = form.into_inner();
+
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(rejection) => {
+            FlashMessage::error(rejection).send();
+            return Ok(see_other("/admin/password/email"));
+        }
+    };
+
+    let username = get_username(*user_id, pool.get_ref())
+        .await
+        .map_err(error_500)?;
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+
+    if let Err(error) = validate_credentials(credentials, &hashing, &pool).await {
+        return match error {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/password/email"))
+            }
+            AuthError::UnexpectedError(_) => Err(error_500(error)),
+        };
+    }
+
+    match set_email(*user_id, &email, &pool).await {
+        Ok(()) => FlashMessage::info("Your email address has been changed.").send(),
+        Err(error) if violated_unique_constraint(&error).is_some() => {
+            FlashMessage::error("Another account already uses that email address.").send()
+        }
+        Err(error) => return Err(error_500(error)),
+    }
+
+    Ok(see_other("/admin/password/email"))
+}
+
+#[tracing::instrument(name = "Get email address", skip(pool))]
+async fn get_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the email address of a user.")?;
+
+    Ok(row.email)
+}
+
+#[tracing::instrument(name = "Set email address", skip(pool))]
+async fn set_email(
+    user_id: Uuid,
+    email: &SubscriberEmail,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE user_id = $2"#,
+        email.as_ref(),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to change the email address of a user.")?;
+
+    Ok(())
+}
@@ -1,66 +1,25 @@
 //! src/routes/admin/password/get.rs
-use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use uuid::Uuid;
 
 use crate::session_state::TypedSession;
+use crate::templates::{read_flash_messages, render, AdminNav, ChangePasswordPage};
 use crate::utils::{error_500, see_other};
 
 pub async fn change_password_form(
     session: TypedSession,
     flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
 ) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(error_500)?.is_none() {
         return Ok(see_other("/login"));
     };
 
-    let mut msg_html = String::new();
-    for msg in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", msg.content()).unwrap();
-    }
-
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Change Password</title>
-            </head>
-            <body>
-                {msg_html}
-                <form action="/admin/password" method="post">
-                    <label>Current password
-                        <input
-                            type="password"
-                            placeholder="Enter current password"
-                            name="current_password"
-                        >
-                    </label>
-                    <br>
-                    <label>New password
-                        <input
-                            type="password"
-                            placeholder="Enter new password"
-                            name="new_password"
-                        >
-                    </label>
-                    <br>
-                    <label>Confirm new password
-                        <input
-                            type="password"
-                            placeholder="Type the new password again"
-                            name="new_password_check"
-                        >
-                    </label>
-                    <br>
-                    <button type="submit">Change password</button>
-                </form>
-                <p><a href="/admin/dashboard">&lt;- Back</a></p>
-            </body>
-            </html>"#
-        )))
+    render(&ChangePasswordPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        idempotency_key: Uuid::new_v4(),
+    })
+    .map_err(error_500)
 }
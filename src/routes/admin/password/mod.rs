@@ -1,7 +1,11 @@
 //! src/routes/admin/password/mod.rs
 
+mod email;
 mod get;
 mod post;
+mod two_factor;
 
+pub use email::{change_email, email_form};
 pub use get::change_password_form;
 pub use post::change_password;
+pub use two_factor::{enroll_two_factor, turn_off_two_factor, two_factor_form};
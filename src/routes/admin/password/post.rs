@@ -1,5 +1,6 @@
 //! src/routes/admin/password/post.rs
 use actix_web::{
+    http::StatusCode,
     web::{self, Data, ReqData},
     HttpResponse,
 };
@@ -8,8 +9,15 @@ use redact::Secret;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    authentication::{
+        revoke_other_sessions, validate_credentials, AuthError, Credentials, PasswordHashing,
+        UserId,
+    },
+    domain::PasswordPolicy,
+    error::Problem,
+    idempotency::IdempotentTransaction,
     routes::admin::dashboard::get_username,
+    session_state::TypedSession,
     utils::{error_500, see_other},
 };
 
@@ -23,41 +31,74 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: Data<PgPool>,
+    transaction: IdempotentTransaction,
     user_id: ReqData<UserId>,
+    password_policy: Data<PasswordPolicy>,
+    hashing: Data<PasswordHashing>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
+        return Ok(reject(
+            StatusCode::BAD_REQUEST,
             "You entered two different new passwords - the field values must match.",
-        )
-        .send();
+        ));
+    }
 
-        return Ok(see_other("/admin/password"));
+    if let Err(rejection) = password_policy.check(&form.new_password) {
+        return Ok(reject(StatusCode::BAD_REQUEST, rejection.to_string()));
     }
 
-    let username = get_username(*user_id, &pool).await.map_err(error_500)?;
+    let mut transaction = transaction.lock().await;
+    let username = get_username(*user_id, &mut **transaction)
+        .await
+        .map_err(error_500)?;
 
     let credentials = Credentials {
         username,
         password: form.0.current_password,
     };
 
-    if let Err(error) = validate_credentials(credentials, &pool).await {
+    if let Err(error) = validate_credentials(credentials, &hashing, &pool).await {
         return match error {
-            AuthError::InvalidCredentials(_) => {
-                FlashMessage::error("The current password is incorrect.").send();
-                Ok(see_other("/admin/password"))
-            }
+            AuthError::InvalidCredentials(_) => Ok(reject(
+                StatusCode::UNAUTHORIZED,
+                "The current password is incorrect.",
+            )),
             AuthError::UnexpectedError(_) => Err(error_500(error)),
         };
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
-        .await
-        .map_err(error_500)?;
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &hashing,
+        &mut **transaction,
+    )
+    .await
+    .map_err(error_500)?;
+
+    // Whoever else knew the old password must not stay logged in with it.
+    if let Some(session_id) = session.get_session_id().map_err(error_500)? {
+        revoke_other_sessions(*user_id, session_id, &mut **transaction)
+            .await
+            .map_err(error_500)?;
+    }
 
     FlashMessage::error("Your password has been changed.").send();
 
     Ok(see_other("/admin/password"))
 }
+
+/// Send the user back to the form with `message`.
+///
+/// The attached problem marks the redirect as a failure, so that it is not saved
+/// as the response to the idempotency key: the user must be able to try again.
+fn reject(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    let message = message.into();
+    FlashMessage::error(message.clone()).send();
+    Problem::new(status)
+        .with_detail(message)
+        .attach_to(see_other("/admin/password"))
+}
@@ -0,0 +1,151 @@
+//! src/routes/admin/password/two_factor.rs
+
+use actix_web::web::{Data, Form, ReqData};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use redact::Secret;
+use sqlx::PgPool;
+
+use crate::authentication::{
+    disable_two_factor, enable_two_factor, generate_recovery_codes, get_totp_secret,
+    validate_credentials, AuthError, Credentials, PasswordHashing, TotpSecret, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::templates::{
+    read_flash_messages, render, AdminNav, RecoveryCodesPage, TwoFactorEnrollment, TwoFactorPage,
+};
+use crate::utils::{error_500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct EnrollFormData {
+    code: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableFormData {
+    current_password: Secret<String>,
+}
+
+pub async fn two_factor_form(
+    session: TypedSession,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let enrollment = if get_totp_secret(*user_id, &pool)
+        .await
+        .map_err(error_500)?
+        .is_some()
+    {
+        None
+    } else {
+        // Keep offering the same secret until it is confirmed, so a mistyped code
+        // does not force the user to scan a new one.
+        let secret = match session.get_pending_totp_secret().map_err(error_500)? {
+            Some(secret) => TotpSecret::parse(secret).map_err(error_500)?,
+            None => {
+                let secret = TotpSecret::generate();
+                session
+                    .insert_pending_totp_secret(secret.expose_secret())
+                    .map_err(error_500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, pool.get_ref())
+            .await
+            .map_err(error_500)?;
+
+        Some(TwoFactorEnrollment {
+            otpauth_uri: secret.otpauth_uri(&username),
+            secret: secret.expose_secret().to_owned(),
+        })
+    };
+
+    render(&TwoFactorPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        enrollment,
+    })
+    .map_err(error_500)
+}
+
+/// Confirm the pending secret with a code from the app, then show the recovery codes once.
+#[tracing::instrument(
+    name = "Enroll in two-factor authentication",
+    skip(form, session, pool)
+)]
+pub async fn enroll_two_factor(
+    form: Form<EnrollFormData>,
+    session: TypedSession,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let Some(secret) = session.get_pending_totp_secret().map_err(error_500)? else {
+        return Ok(see_other("/admin/password/two-factor"));
+    };
+    let secret = TotpSecret::parse(secret).map_err(error_500)?;
+
+    if secret
+        .verify(&form.code, chrono::Utc::now().timestamp() as u64)
+        .is_none()
+    {
+        FlashMessage::error("The code is incorrect.").send();
+        return Ok(see_other("/admin/password/two-factor"));
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    enable_two_factor(*user_id, &secret, &recovery_codes, &pool)
+        .await
+        .map_err(error_500)?;
+    session.remove_pending_totp_secret();
+
+    render(&RecoveryCodesPage {
+        nav,
+        messages: Vec::new(),
+        recovery_codes,
+    })
+    .map_err(error_500)
+}
+
+#[tracing::instrument(name = "Turn off two-factor authentication", skip(form, pool, hashing))]
+pub async fn turn_off_two_factor(
+    form: Form<DisableFormData>,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    hashing: Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let username = get_username(*user_id, pool.get_ref())
+        .await
+        .map_err(error_500)?;
+    let credentials = Credentials {
+        username,
+        password: form.into_inner().current_password,
+    };
+
+    if let Err(error) = validate_credentials(credentials, &hashing, &pool).await {
+        return match error {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/password/two-factor"))
+            }
+            AuthError::UnexpectedError(_) => Err(error_500(error)),
+        };
+    }
+
+    disable_two_factor(*user_id, &pool)
+        .await
+        .map_err(error_500)?;
+
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+
+    Ok(see_other("/admin/password/two-factor"))
+}
@@ -0,0 +1,75 @@
+//! src/routes/admin/sessions.rs
+
+use actix_web::web::{Data, Form, ReqData};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{get_active_sessions, revoke_other_sessions, revoke_session, UserId};
+use crate::session_state::TypedSession;
+use crate::templates::{read_flash_messages, render, AdminNav, SessionsPage};
+use crate::utils::{error_500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RevokeSessionFormData {
+    session_id: Uuid,
+}
+
+pub async fn list_sessions(
+    session: TypedSession,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(error_500)?;
+    let sessions = get_active_sessions(**user_id, &pool)
+        .await
+        .map_err(error_500)?;
+
+    render(&SessionsPage {
+        nav,
+        messages: read_flash_messages(&flash_messages),
+        sessions,
+        current_session_id,
+    })
+    .map_err(error_500)
+}
+
+#[tracing::instrument(name = "Revoke one of my sessions", skip(form, pool))]
+pub async fn revoke_one_session(
+    form: Form<RevokeSessionFormData>,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Scoped to the current user: the ids of other users' sessions revoke nothing.
+    if revoke_session(**user_id, form.session_id, &pool)
+        .await
+        .map_err(error_500)?
+    {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("That session does not exist or has already ended.").send();
+    }
+
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Revoke all my other sessions", skip(session, pool))]
+pub async fn revoke_all_other_sessions(
+    session: TypedSession,
+    pool: Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(session_id) = session.get_session_id().map_err(error_500)? else {
+        return Ok(see_other("/login"));
+    };
+
+    let revoked = revoke_other_sessions(**user_id, session_id, pool.get_ref())
+        .await
+        .map_err(error_500)?;
+    FlashMessage::info(format!("{revoked} other session(s) have been logged out.")).send();
+
+    Ok(see_other("/admin/sessions"))
+}
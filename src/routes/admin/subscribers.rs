@@ -0,0 +1,123 @@
+//! src/routes/admin/subscribers.rs
+
+use actix_web::web::{Data, Path};
+use actix_web::HttpResponse;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::templates::{render, AdminNav, SubscriberPage, SubscribersPage};
+use crate::utils::error_500;
+
+pub struct SubscriberSummary {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+}
+
+pub struct SubscriberDetails {
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub source: Option<String>,
+    pub consent_text_version: Option<String>,
+    pub source_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub consented_at: Option<DateTime<Utc>>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub confirmation_ip: Option<String>,
+}
+
+pub async fn list_subscribers(
+    pool: Data<PgPool>,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = get_subscribers(&pool).await.map_err(error_500)?;
+
+    render(&SubscribersPage {
+        nav,
+        messages: Vec::new(),
+        subscribers,
+    })
+    .map_err(error_500)
+}
+
+pub async fn subscriber_details(
+    subscriber_id: Path<Uuid>,
+    pool: Data<PgPool>,
+    nav: AdminNav,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+
+    let subscriber = match get_subscriber_details(subscriber_id, &pool)
+        .await
+        .map_err(error_500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    render(&SubscriberPage {
+        nav,
+        messages: Vec::new(),
+        subscriber,
+    })
+    .map_err(error_500)
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(pool: &PgPool) -> Result<Vec<SubscriberSummary>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status
+        FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?;
+
+    Ok(subscribers)
+}
+
+#[tracing::instrument(name = "Get subscriber details", skip(pool))]
+async fn get_subscriber_details(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let details = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            c.source as "source?",
+            c.consent_text_version as "consent_text_version?",
+            c.source_ip,
+            c.user_agent,
+            c.consented_at as "consented_at?",
+            c.confirmed_at,
+            c.confirmation_ip
+        FROM subscriptions s
+        LEFT JOIN subscription_consents c ON c.subscriber_id = s.id
+        WHERE s.id = $1
+        -- Subscribers merged with their duplicates have several consents: show the one
+        -- that was confirmed first, or else the first one given.
+        ORDER BY c.confirmed_at ASC NULLS LAST, c.consented_at ASC
+        LIMIT 1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscriber details.")?;
+
+    Ok(details)
+}
@@ -0,0 +1,7 @@
+//! src/routes/api/mod.rs
+
+mod newsletters;
+mod subscriptions;
+
+pub use newsletters::*;
+pub use subscriptions::*;
@@ -0,0 +1,91 @@
+//! src/routes/api/newsletters.rs
+
+use actix_web::http::StatusCode;
+use actix_web::web::Json;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::authentication::{ApiAuthError, ApiScope, ApiUser, Role};
+use crate::error::Problem;
+use crate::idempotency::IdempotentTransaction;
+use crate::routes::{enqueue_delivery_tasks, error_chain_fmt, insert_newsletter_issue};
+
+#[derive(serde::Deserialize)]
+pub struct PublishRequest {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[derive(serde::Serialize)]
+struct PublishResponse {
+    id: Uuid,
+    status: &'static str,
+}
+
+/// Publish a newsletter issue on behalf of the owner of an API token.
+///
+/// Retries with the same `Idempotency-Key` get the original response back instead of
+/// publishing the issue twice, exactly like resubmissions of the admin form. Reusing a
+/// key for a different issue is rejected with `422 Unprocessable Entity`.
+#[tracing::instrument(
+    name = "Publish a newsletter issue via the API",
+    skip_all,
+    fields(user_id = %user.user_id)
+)]
+pub async fn api_publish_newsletter(
+    body: Json<PublishRequest>,
+    user: ApiUser,
+    transaction: IdempotentTransaction,
+) -> Result<HttpResponse, ApiPublishError> {
+    user.require(ApiScope::PublishNewsletters, Role::Editor)?;
+    let mut transaction = transaction.lock().await;
+
+    let PublishRequest {
+        title,
+        text_content,
+        html_content,
+    } = body.into_inner();
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    Ok(HttpResponse::Accepted().json(PublishResponse {
+        id: issue_id,
+        status: "queued",
+    }))
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiPublishError {
+    #[error(transparent)]
+    AuthError(#[from] ApiAuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiPublishError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for ApiPublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::AuthError(error) => error.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::AuthError(error) => error.error_response(),
+            Self::UnexpectedError(_) => Problem::new(self.status_code()).into_response(),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! src/routes/api/subscriptions.rs
+
+use actix_web::error::JsonPayloadError;
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Json, JsonConfig};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use uuid::Uuid;
+
+use crate::domain::{
+    ConsentSource, EmailScreening, NewSubscriber, SubscriberEmail, SubscriberName,
+    SubscriptionConsent,
+};
+use crate::email_client::EmailClient;
+use crate::error::{InvalidParam, Problem};
+use crate::idempotency::IdempotentTransaction;
+use crate::routes::{error_chain_fmt, register_subscriber, RegisterSubscriberError};
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriptionRequest {
+    pub email: String,
+    pub name: String,
+    /// Subscribe `email` as is, even though a correction was suggested for it.
+    #[serde(default)]
+    pub keep_email: bool,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionResponse {
+    id: Uuid,
+    status: &'static str,
+}
+
+/// Register a subscriber on their behalf.
+///
+/// Calling this endpoint records that the subscriber agreed to [`CONSENT_TEXT`] as of
+/// [`CONSENT_TEXT_VERSION`]: clients must show them that wording before submitting.
+///
+/// Unlike the HTML form, the endpoint takes no form token nor honeypot, which only make
+/// sense for pages served by the application. Bots are held back by the rate limit of
+/// the route and by the same email screening as the form.
+///
+/// [`CONSENT_TEXT`]: crate::domain::CONSENT_TEXT
+/// [`CONSENT_TEXT_VERSION`]: crate::domain::CONSENT_TEXT_VERSION
+#[tracing::instrument(
+    name = "Adding a new subscriber via the API",
+    skip(body, transaction, email_client, base_url, email_screening, request),
+    fields(
+        subscriber_email = %body.email,
+        subscriber_name = %body.name
+    )
+)]
+pub async fn api_subscribe(
+    body: Json<SubscriptionRequest>,
+    transaction: IdempotentTransaction,
+    email_client: Data<EmailClient>,
+    base_url: Data<ApplicationBaseUrl>,
+    email_screening: Data<EmailScreening>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiSubscribeError> {
+    let new_subscriber = parse_subscription_request(body.into_inner(), &email_screening).await?;
+    let consent = SubscriptionConsent::from_request(&request, ConsentSource::Api);
+
+    let mut transaction = transaction.lock().await;
+    let subscriber_id = register_subscriber(
+        &mut transaction,
+        &email_client,
+        &base_url,
+        new_subscriber,
+        &consent,
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(SubscriptionResponse {
+        id: subscriber_id,
+        status: "pending_confirmation",
+    }))
+}
+
+/// Validate every field up front so that clients learn about all of their mistakes at once.
+async fn parse_subscription_request(
+    request: SubscriptionRequest,
+    email_screening: &EmailScreening,
+) -> Result<NewSubscriber, ApiSubscribeError> {
+    let name =
+        SubscriberName::parse(request.name).map_err(|reason| InvalidParam::new("name", reason));
+    let email = match SubscriberEmail::parse(request.email) {
+        Ok(email) => match email_screening.screen(&email, request.keep_email).await {
+            Ok(()) => Ok(email),
+            Err(rejection) => Err(InvalidParam::from(&rejection)),
+        },
+        Err(reason) => Err(InvalidParam::new("email", reason)),
+    };
+
+    match (name, email) {
+        (Ok(name), Ok(email)) => Ok(NewSubscriber { email, name }),
+        (name, email) => {
+            let errors = [name.err(), email.err()].into_iter().flatten().collect();
+            Err(ApiSubscribeError::ValidationError(errors))
+        }
+    }
+}
+
+/// Report malformed or incomplete JSON bodies with the same shape as validation errors.
+pub fn api_json_config() -> JsonConfig {
+    JsonConfig::default().error_handler(|error, _request| {
+        let message = match &error {
+            JsonPayloadError::Deserialize(error) => error.to_string(),
+            other => other.to_string(),
+        };
+        ApiSubscribeError::MalformedBody(message).into()
+    })
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiSubscribeError {
+    #[error("The request body is not valid: {0}")]
+    MalformedBody(String),
+    #[error("One or more fields are invalid.")]
+    ValidationError(Vec<InvalidParam>),
+    #[error("This email address is already subscribed.")]
+    AlreadySubscribed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiSubscribeError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for ApiSubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MalformedBody(_) | Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadySubscribed => StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            Self::MalformedBody(_) => problem
+                .with_type("/problems/malformed-body")
+                .with_detail(self.to_string()),
+            Self::ValidationError(errors) => problem
+                .with_type("/problems/validation-error")
+                .with_detail(self.to_string())
+                .with_invalid_params(errors.clone()),
+            Self::AlreadySubscribed => problem
+                .with_type("/problems/already-subscribed")
+                .with_detail(self.to_string()),
+            Self::UnexpectedError(_) => problem,
+        }
+        .into_response()
+    }
+}
+
+impl From<RegisterSubscriberError> for ApiSubscribeError {
+    fn from(error: RegisterSubscriberError) -> Self {
+        match error {
+            RegisterSubscriberError::AlreadySubscribed => Self::AlreadySubscribed,
+            RegisterSubscriberError::UnexpectedError(error) => Self::UnexpectedError(error),
+        }
+    }
+}
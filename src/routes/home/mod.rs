@@ -1,7 +1,12 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::web::Data;
+use actix_web::HttpResponse;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+use crate::routes::SubscriptionFormGuard;
+use crate::templates::{render, HomePage};
+use crate::utils::error_500;
+
+pub async fn home(
+    form_guard: Data<SubscriptionFormGuard>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render(&HomePage::new(form_guard.issue_token())).map_err(error_500)
 }
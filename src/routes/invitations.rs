@@ -0,0 +1,254 @@
+//! src/routes/invitations.rs
+
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Form, Query};
+use actix_web::{HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use redact::Secret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::authentication::{create_user, PasswordHashing, Role};
+use crate::domain::PasswordPolicy;
+use crate::error::Problem;
+use crate::routes::error_chain_fmt;
+use crate::templates::{read_flash_messages, render, AcceptInvitationPage};
+use crate::utils::{hash_token, see_other, violated_unique_constraint};
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParameters {
+    invitation_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInvitationFormData {
+    invitation_token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Show the invitation form",
+    skip(parameters, pool, flash_messages)
+)]
+pub async fn accept_invitation_form(
+    parameters: Query<InvitationParameters>,
+    pool: Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, InvitationError> {
+    let email = get_invitation_email(&pool, &parameters.invitation_token)
+        .await?
+        .ok_or(InvitationError::UnknownToken)?;
+
+    let page = AcceptInvitationPage {
+        messages: read_flash_messages(&flash_messages),
+        email,
+        invitation_token: parameters.into_inner().invitation_token,
+    };
+    render(&page)
+        .context("Failed to render the invitation form")
+        .map_err(InvitationError::UnexpectedError)
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, pool, password_policy, hashing),
+    fields(username = %form.username, user_id = tracing::field::Empty)
+)]
+pub async fn accept_invitation(
+    form: Form<AcceptInvitationFormData>,
+    pool: Data<PgPool>,
+    password_policy: Data<PasswordPolicy>,
+    hashing: Data<PasswordHashing>,
+) -> Result<HttpResponse, InvitationError> {
+    let form = form.into_inner();
+    let retry_location = format!(
+        "/invitations/accept?invitation_token={}",
+        urlencoding::encode(&form.invitation_token)
+    );
+    let username = form.username.trim();
+
+    if username.is_empty() {
+        FlashMessage::error("Please choose a username.").send();
+        return Ok(see_other(&retry_location));
+    }
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&retry_location));
+    }
+    if let Err(rejection) = password_policy.check(&form.password) {
+        FlashMessage::error(rejection.to_string()).send();
+        return Ok(see_other(&retry_location));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let (email, role) = get_invitation_for_update(&mut transaction, &form.invitation_token)
+        .await?
+        .ok_or(InvitationError::UnknownToken)?;
+
+    if username_is_taken(&mut transaction, username).await? {
+        FlashMessage::error("That username is already taken.").send();
+        return Ok(see_other(&retry_location));
+    }
+
+    let user_id = match create_user(
+        &mut transaction,
+        username,
+        &email,
+        role,
+        form.password,
+        &hashing,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        // Another account was created with the same username or email since the check
+        // above, or the email was invited twice and the other invitation got accepted.
+        Err(error) => match violated_unique_constraint(&error) {
+            Some("users_username_key") => {
+                FlashMessage::error("That username is already taken.").send();
+                return Ok(see_other(&retry_location));
+            }
+            Some("users_email_key" | "users_email_canonical_key") => {
+                return Err(InvitationError::AlreadyAccepted(email))
+            }
+            _ => return Err(error.into()),
+        },
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    mark_invitation_as_accepted(&mut transaction, &form.invitation_token, user_id).await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a new user.")?;
+
+    FlashMessage::info("Your account has been created. You can now log in.").send();
+
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(name = "Get the email of a pending invitation", skip(pool, token))]
+async fn get_invitation_email(pool: &PgPool, token: &str) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM user_invitations
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > now()
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve an invitation.")?;
+
+    Ok(row.map(|row| row.email))
+}
+
+/// Lock the invitation so that the same link cannot create two accounts.
+#[tracing::instrument(name = "Lock a pending invitation", skip(transaction, token))]
+async fn get_invitation_for_update(
+    transaction: &mut Transaction<'static, Postgres>,
+    token: &str,
+) -> Result<Option<(String, Role)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role
+        FROM user_invitations
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > now()
+        FOR UPDATE
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve an invitation.")?;
+
+    row.map(|row| {
+        let role = Role::try_from(row.role).map_err(anyhow::Error::msg)?;
+        Ok((row.email, role))
+    })
+    .transpose()
+}
+
+#[tracing::instrument(name = "Check whether a username is taken", skip(transaction))]
+async fn username_is_taken(
+    transaction: &mut Transaction<'static, Postgres>,
+    username: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE username = $1) AS "taken!""#,
+        username
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .context("Failed to look up users by username.")?;
+
+    Ok(row.taken)
+}
+
+#[tracing::instrument(name = "Mark an invitation as accepted", skip(transaction, token))]
+async fn mark_invitation_as_accepted(
+    transaction: &mut Transaction<'static, Postgres>,
+    token: &str,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now(), accepted_by = $2
+        WHERE token_hash = $1
+        "#,
+        hash_token(token),
+        user_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to mark an invitation as accepted.")?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error("This invitation is invalid, has expired or has already been used.")]
+    UnknownToken,
+    #[error("{0} already has an account.")]
+    AlreadyAccepted(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for InvitationError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for InvitationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::AlreadyAccepted(_) => StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            Self::UnknownToken | Self::AlreadyAccepted(_) => problem.with_detail(self.to_string()),
+            Self::UnexpectedError(_) => problem,
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,148 @@
+//! src/routes/login/forgot.rs
+
+use actix_web::web::{Data, Form};
+use actix_web::HttpResponse;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{TimeDelta, Utc};
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::templates::{read_flash_messages, render, ForgotPasswordPage};
+use crate::utils::{error_500, generate_token, hash_token, see_other};
+
+/// How long a password reset link can be used for.
+pub const RESET_TOKEN_VALIDITY: TimeDelta = TimeDelta::hours(1);
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    email: String,
+}
+
+pub async fn forgot_password_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render(&ForgotPasswordPage {
+        messages: read_flash_messages(&flash_messages),
+    })
+    .map_err(error_500)
+}
+
+/// Email a password reset link to the owner of the account, if there is one.
+///
+/// The response is the same whether or not the email belongs to an account, and
+/// failures are only logged, so that the form cannot be used to discover accounts.
+/// The link is sent in the background: otherwise the response would take longer
+/// for the emails that do belong to an account.
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url),
+    fields(email = %form.email)
+)]
+pub async fn forgot_password(
+    form: Form<ForgotPasswordFormData>,
+    pool: Data<PgPool>,
+    email_client: Data<EmailClient>,
+    base_url: Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    if let Ok(email) = SubscriberEmail::parse(form.into_inner().email) {
+        let pool = pool.get_ref().clone();
+        tokio::spawn(
+            async move {
+                if let Err(error) = send_reset_link(&pool, &email_client, &base_url.0, &email).await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?error,
+                        error.message = %error,
+                        "Failed to send a password reset link"
+                    );
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+    }
+
+    FlashMessage::info(
+        "If an account uses that email address, we have sent it a link to reset the password.",
+    )
+    .send();
+
+    see_other("/login/forgot")
+}
+
+async fn send_reset_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let Some(user_id) = get_user_id_by_email(pool, email).await? else {
+        return Ok(());
+    };
+
+    let reset_token = generate_token(32);
+    store_reset_token(pool, &reset_token, user_id).await?;
+
+    let reset_link = format!("{}/login/reset?reset_token={}", base_url, reset_token);
+    let html_email = format!(
+        "Someone asked to reset the password of your account.<br />\
+        Click <a href=\"{}\">here</a> to choose a new one. \
+        If it wasn't you, you can ignore this email.",
+        reset_link
+    );
+    let text_email = format!(
+        "Someone asked to reset the password of your account.\nVisit {} to choose a new one. \
+        If it wasn't you, you can ignore this email.",
+        reset_link
+    );
+
+    email_client
+        .send_email(email, "Reset your password", &html_email, &text_email)
+        .await
+        .context("Failed to send a password reset email.")
+}
+
+#[tracing::instrument(name = "Get a user by email", skip(pool))]
+async fn get_user_id_by_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up users by email.")?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+#[tracing::instrument(name = "Store a password reset token", skip(pool, reset_token))]
+async fn store_reset_token(
+    pool: &PgPool,
+    reset_token: &str,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_token(reset_token),
+        user_id,
+        now,
+        now + RESET_TOKEN_VALIDITY
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a password reset token in the database.")?;
+
+    Ok(())
+}
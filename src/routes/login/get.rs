@@ -1,43 +1,14 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
+use crate::templates::{read_flash_messages, render, LoginPage};
+use crate::utils::error_500;
 
-    for message in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", message.content()).unwrap();
-    }
-
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-                <html lang="en">
-                <head>
-                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                    <title>Login</title>
-                </head>
-                <body>
-                    {error_html}
-                    <form action="/login" method="post">
-                        <label>Username
-                            <input
-                                type="text"
-                                placeholder="Enter Username"
-                                name="username"
-                            >
-                        </label>
-                        <label>Password
-                            <input
-                                type="password"
-                                placeholder="Enter Password"
-                                name="password"
-                            >
-                        </label>
-                        <button type="submit">Login</button>
-                    </form>
-                </body>
-                </html>"#,
-        ))
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render(&LoginPage {
+        messages: read_flash_messages(&flash_messages),
+    })
+    .map_err(error_500)
 }
@@ -4,14 +4,18 @@ use actix_web::{
     error::InternalError,
     http::{header::LOCATION, StatusCode},
     web::{Data, Form},
-    HttpResponse, ResponseError,
+    HttpRequest, HttpResponse, ResponseError,
 };
 use actix_web_flash_messages::FlashMessage;
 use redact::Secret;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    authentication::{
+        get_totp_secret, record_login_attempt, start_session, validate_credentials, AuthError,
+        Credentials, LoginLockout, LoginOutcome, PasswordHashing, SessionClient,
+    },
+    error::Problem,
     routes::error_chain_fmt,
     session_state::TypedSession,
 };
@@ -23,32 +27,87 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, lockout, hashing, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: Form<FormData>,
     pool: Data<PgPool>,
     session: TypedSession,
+    lockout: Data<LoginLockout>,
+    hashing: Data<PasswordHashing>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let username = form.0.username;
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
+    let client = SessionClient::from_request(&request);
 
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&username));
 
-    match validate_credentials(credentials, &pool).await {
+    let locked_out = lockout
+        .is_locked_out(&username, &pool)
+        .await
+        .map_err(|error| login_redirect(LoginError::UnexpectedError(error)))?;
+
+    // The password is checked even for locked usernames: skipping the (slow) hash
+    // would make locked usernames answer faster and reveal which ones are being attacked.
+    let result = validate_credentials(credentials, &hashing, &pool).await;
+
+    let two_factor_pending = match (&result, locked_out) {
+        (Ok(user_id), false) => get_totp_secret(*user_id, &pool)
+            .await
+            .map_err(|error| login_redirect(LoginError::UnexpectedError(error)))?
+            .is_some(),
+        _ => false,
+    };
+
+    let outcome = match (&result, locked_out) {
+        (Err(AuthError::UnexpectedError(_)), _) => None,
+        (_, true) => Some(LoginOutcome::LockedOut),
+        // The second step records the outcome: a right password alone must not
+        // reset the count of wrong codes.
+        (Ok(_), false) if two_factor_pending => None,
+        (Ok(_), false) => Some(LoginOutcome::Success),
+        (Err(AuthError::InvalidCredentials(_)), false) => Some(LoginOutcome::Failure),
+    };
+    if let Some(outcome) = outcome {
+        let user_id = result.as_ref().ok().copied();
+        record_login_attempt(&username, user_id, client.ip.as_deref(), outcome, &pool)
+            .await
+            .map_err(|error| login_redirect(LoginError::UnexpectedError(error)))?;
+    }
+
+    if locked_out {
+        return Err(login_redirect(LoginError::LockedOut));
+    }
+
+    match result {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
             session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|error| login_redirect(LoginError::UnexpectedError(error.into())))?;
+
+            // With two-factor authentication the user is only logged in once the code checks out.
+            let location = if two_factor_pending {
+                session
+                    .insert_pending_user_id(user_id)
+                    .map_err(|error| login_redirect(LoginError::UnexpectedError(error.into())))?;
+                "/login/two-factor"
+            } else {
+                let session_id = start_session(user_id, &client, &pool)
+                    .await
+                    .map_err(|error| login_redirect(LoginError::UnexpectedError(error)))?;
+                session
+                    .insert_user_id(user_id, session_id)
+                    .map_err(|error| login_redirect(LoginError::UnexpectedError(error.into())))?;
+                "/admin/dashboard"
+            };
 
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, location))
                 .finish())
         }
         Err(error) => {
@@ -66,6 +125,8 @@ pub async fn login(
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts. Please try again later.")]
+    LockedOut,
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -84,9 +145,20 @@ impl ResponseError for LoginError {
 
 fn login_redirect(error: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(error.to_string()).send();
-    let response = HttpResponse::SeeOther()
-        .insert_header((LOCATION, "/login"))
-        .finish();
+    let problem = match &error {
+        LoginError::AuthError(_) => {
+            Problem::new(StatusCode::UNAUTHORIZED).with_detail(error.to_string())
+        }
+        LoginError::LockedOut => {
+            Problem::new(StatusCode::TOO_MANY_REQUESTS).with_detail(error.to_string())
+        }
+        LoginError::UnexpectedError(_) => Problem::new(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    let response = problem.attach_to(
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login"))
+            .finish(),
+    );
 
     InternalError::from_response(error, response)
 }
@@ -0,0 +1,200 @@
+//! src/routes/login/reset.rs
+
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Form, Query};
+use actix_web::{HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use redact::Secret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::authentication::{change_password, revoke_sessions, PasswordHashing};
+use crate::domain::PasswordPolicy;
+use crate::error::Problem;
+use crate::routes::error_chain_fmt;
+use crate::templates::{read_flash_messages, render, ResetPasswordPage};
+use crate::utils::{hash_token, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ResetParameters {
+    reset_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    reset_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Show the password reset form",
+    skip(parameters, pool, flash_messages)
+)]
+pub async fn reset_password_form(
+    parameters: Query<ResetParameters>,
+    pool: Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, ResetPasswordError> {
+    if !reset_token_is_valid(&pool, &parameters.reset_token).await? {
+        return Err(ResetPasswordError::UnknownToken);
+    }
+
+    let page = ResetPasswordPage {
+        messages: read_flash_messages(&flash_messages),
+        reset_token: parameters.into_inner().reset_token,
+    };
+    render(&page)
+        .context("Failed to render the password reset form")
+        .map_err(ResetPasswordError::UnexpectedError)
+}
+
+#[tracing::instrument(
+    name = "Reset a password",
+    skip(form, pool, password_policy, hashing),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn reset_password(
+    form: Form<ResetPasswordFormData>,
+    pool: Data<PgPool>,
+    password_policy: Data<PasswordPolicy>,
+    hashing: Data<PasswordHashing>,
+) -> Result<HttpResponse, ResetPasswordError> {
+    let form = form.into_inner();
+    let retry_location = format!(
+        "/login/reset?reset_token={}",
+        urlencoding::encode(&form.reset_token)
+    );
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&retry_location));
+    }
+    if let Err(rejection) = password_policy.check(&form.new_password) {
+        FlashMessage::error(rejection.to_string()).send();
+        return Ok(see_other(&retry_location));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let user_id = get_user_id_for_update(&mut transaction, &form.reset_token)
+        .await?
+        .ok_or(ResetPasswordError::UnknownToken)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    change_password(user_id, form.new_password, &hashing, &mut *transaction).await?;
+    // Any other link that was sent out for this account is now stale as well.
+    mark_reset_tokens_as_used(&mut transaction, user_id).await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password.")?;
+
+    revoke_sessions(user_id, &pool).await?;
+
+    FlashMessage::info("Your password has been reset. You can now log in.").send();
+
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(name = "Check a password reset token", skip(pool, token))]
+async fn reset_token_is_valid(pool: &PgPool, token: &str) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM password_reset_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        ) AS "valid!"
+        "#,
+        hash_token(token)
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve a password reset token.")?;
+
+    Ok(row.valid)
+}
+
+/// Lock the token so that the same link cannot be used twice concurrently.
+#[tracing::instrument(name = "Lock a password reset token", skip(transaction, token))]
+async fn get_user_id_for_update(
+    transaction: &mut Transaction<'static, Postgres>,
+    token: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        FOR UPDATE
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve a password reset token.")?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+#[tracing::instrument(name = "Mark password reset tokens as used", skip(transaction))]
+async fn mark_reset_tokens_as_used(
+    transaction: &mut Transaction<'static, Postgres>,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to mark password reset tokens as used.")?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum ResetPasswordError {
+    #[error("This password reset link is invalid, has expired or has already been used.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResetPasswordError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
+impl ResponseError for ResetPasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            Self::UnknownToken => problem.with_detail(self.to_string()),
+            Self::UnexpectedError(_) => problem,
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,110 @@
+//! src/routes/login/two_factor.rs
+
+use actix_web::web::{Data, Form};
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+
+use crate::authentication::{
+    record_login_attempt, start_session, verify_second_factor, LoginLockout, LoginOutcome,
+    SessionClient,
+};
+use crate::routes::get_username;
+use crate::session_state::TypedSession;
+use crate::templates::{read_flash_messages, render, LoginTwoFactorPage};
+use crate::utils::{error_500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: String,
+}
+
+pub async fn login_two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(error_500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    render(&LoginTwoFactorPage {
+        messages: read_flash_messages(&flash_messages),
+    })
+    .map_err(error_500)
+}
+
+/// Wrong codes count as failed logins, so guessing them locks the username
+/// just like guessing passwords does.
+#[tracing::instrument(
+    name = "Verify the second login step",
+    skip(form, session, pool, lockout, request),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    form: Form<TwoFactorFormData>,
+    session: TypedSession,
+    pool: Data<PgPool>,
+    lockout: Data<LoginLockout>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_pending_user_id().map_err(error_500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let username = get_username(user_id, pool.get_ref())
+        .await
+        .map_err(error_500)?;
+    let client = SessionClient::from_request(&request);
+
+    if lockout
+        .is_locked_out(&username, &pool)
+        .await
+        .map_err(error_500)?
+    {
+        record_login_attempt(
+            &username,
+            Some(user_id),
+            client.ip.as_deref(),
+            LoginOutcome::LockedOut,
+            &pool,
+        )
+        .await
+        .map_err(error_500)?;
+        session.log_out();
+        FlashMessage::error("Too many failed login attempts. Please try again later.").send();
+        return Ok(see_other("/login"));
+    }
+
+    let verified = verify_second_factor(user_id, &form.code, &pool)
+        .await
+        .map_err(error_500)?;
+    let outcome = if verified {
+        LoginOutcome::Success
+    } else {
+        LoginOutcome::Failure
+    };
+    record_login_attempt(
+        &username,
+        Some(user_id),
+        client.ip.as_deref(),
+        outcome,
+        &pool,
+    )
+    .await
+    .map_err(error_500)?;
+
+    if !verified {
+        FlashMessage::error("The code is incorrect.").send();
+        return Ok(see_other("/login/two-factor"));
+    }
+
+    let session_id = start_session(user_id, &client, &pool)
+        .await
+        .map_err(error_500)?;
+    session.renew();
+    session
+        .insert_user_id(user_id, session_id)
+        .map_err(error_500)?;
+
+    Ok(see_other("/admin/dashboard"))
+}
@@ -1,13 +1,17 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
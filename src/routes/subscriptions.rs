@@ -1,29 +1,54 @@
 //! src/routes/subscriptions
 
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use actix_web::web::{Data, Form};
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use chrono::Utc;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use askama::Template;
+use chrono::{TimeDelta, Utc};
+use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
-use crate::domain::NewSubscriber;
+use crate::configuration::SubscriptionFormSettings;
+use crate::domain::{
+    ConsentSource, EmailRejection, EmailScreening, FormToken, NewSubscriber, SubscriptionConsent,
+};
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::error::{InvalidParam, Problem};
+use crate::idempotency::IdempotentTransaction;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::templates::HomePage;
+use crate::utils::violated_unique_constraint;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    /// Honeypot: hidden from humans, so anything in it came from a bot.
+    #[serde(default)]
+    pub website: String,
+    #[serde(default)]
+    pub form_token: String,
+    /// Set when resubmitting an address that was flagged as a likely typo.
+    #[serde(default)]
+    pub keep_email: bool,
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        form,
+        transaction,
+        email_client,
+        base_url,
+        form_guard,
+        email_screening,
+        request
+    ),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -31,33 +56,152 @@ pub struct FormData {
 )]
 pub async fn subscribe(
     form: Form<FormData>,
-    pool: Data<PgPool>,
+    transaction: IdempotentTransaction,
     email_client: Data<EmailClient>,
     base_url: Data<ApplicationBaseUrl>,
+    form_guard: Data<SubscriptionFormGuard>,
+    email_screening: Data<EmailScreening>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    // Bots are told that everything went fine so that they have nothing to adapt to.
+    if let Err(reason) = form_guard.check(&form) {
+        tracing::warn!(reason, "Discarding a subscription that looks automated");
+        return Ok(HttpResponse::Ok().finish());
+    }
 
-    let mut transaction = pool
-        .begin()
+    let keep_email = form.keep_email;
+    let typed = HomePage {
+        name: form.name.clone(),
+        email: form.email.clone(),
+        ..HomePage::new(form_guard.issue_token())
+    };
+    let new_subscriber: NewSubscriber =
+        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    match email_screening
+        .screen(&new_subscriber.email, keep_email)
         .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
+    {
+        Ok(()) => {}
+        Err(rejection @ EmailRejection::LikelyTypo { .. }) => {
+            return ask_to_correct_email(typed, rejection)
+        }
+        Err(rejection) => return Err(SubscribeError::RejectedEmail(rejection)),
+    }
+    let consent = SubscriptionConsent::from_request(&request, ConsentSource::Form);
+
+    let mut transaction = transaction.lock().await;
+    register_subscriber(
+        &mut transaction,
+        &email_client,
+        &base_url,
+        new_subscriber,
+        &consent,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Show the form again with the suggested correction, offering to keep the address as typed.
+fn ask_to_correct_email(
+    typed: HomePage,
+    rejection: EmailRejection,
+) -> Result<HttpResponse, SubscribeError> {
+    let page = HomePage {
+        messages: vec![rejection.to_string()],
+        ask_to_keep_email: true,
+        ..typed
+    };
+    let html = page
+        .render()
+        .context("Failed to render the subscription form.")?;
+    let problem = Problem::new(StatusCode::BAD_REQUEST)
+        .with_detail(rejection.to_string())
+        .with_invalid_params(vec![(&rejection).into()]);
+
+    Ok(problem.attach_to(
+        HttpResponse::BadRequest()
+            .content_type(ContentType::html())
+            .body(html),
+    ))
+}
+
+/// Tells human submissions of the subscription form apart from automated ones.
+pub struct SubscriptionFormGuard {
+    hmac_secret: HmacSecret,
+    settings: SubscriptionFormSettings,
+}
+
+impl SubscriptionFormGuard {
+    pub fn new(hmac_secret: HmacSecret, settings: SubscriptionFormSettings) -> Self {
+        Self {
+            hmac_secret,
+            settings,
+        }
+    }
+
+    /// A token for a freshly rendered form.
+    pub fn issue_token(&self) -> String {
+        FormToken::new(Utc::now()).sign(&self.hmac_secret.0)
+    }
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    /// Reject submissions that filled the honeypot, lack a valid form token or were
+    /// posted faster than a human could have filled the form in.
+    fn check(&self, form: &FormData) -> Result<(), String> {
+        if !form.website.is_empty() {
+            return Err("The honeypot field was filled in.".into());
+        }
+
+        let token = FormToken::verify(&form.form_token, &self.hmac_secret.0)?;
+        let elapsed = Utc::now() - token.issued_at();
+
+        if elapsed < TimeDelta::seconds(self.settings.min_fill_seconds as i64) {
+            return Err("The form was submitted too quickly after being rendered.".into());
+        }
+        if elapsed > TimeDelta::seconds(self.settings.max_age_seconds as i64) {
+            return Err("The form token has expired.".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Store a new pending subscriber alongside their consent and mail them a confirmation link.
+///
+/// Shared by the HTML form and the JSON API so that both paths behave identically.
+/// The writes go through the transaction of the request, which commits them.
+pub async fn register_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    new_subscriber: NewSubscriber,
+    consent: &SubscriptionConsent,
+) -> Result<Uuid, RegisterSubscriberError> {
+    let subscriber_id = match insert_subscriber(transaction, &new_subscriber)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to insert new subscriber in the database.")
+    {
+        Ok(subscriber_id) => subscriber_id,
+        // Addresses that only differ by case reach the same mailbox.
+        Err(error)
+            if violated_unique_constraint(&error) == Some("subscriptions_email_canonical_key") =>
+        {
+            return Err(RegisterSubscriberError::AlreadySubscribed)
+        }
+        Err(error) => return Err(error.into()),
+    };
 
-    let subscription_token = ganerate_subscription_token();
-    store_subscription_token(&mut transaction, subscriber_id, &subscription_token)
+    insert_subscription_consent(transaction, subscriber_id, consent)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to record the consent of a new subscriber.")?;
 
-    transaction
-        .commit()
+    let subscription_token = ganerate_subscription_token();
+    store_subscription_token(transaction, subscriber_id, &subscription_token)
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        .context("Failed to store the confirmation token for a new subscriber.")?;
 
     send_confirmation_email(
-        &email_client,
+        email_client,
         new_subscriber,
         &base_url.0,
         &subscription_token,
@@ -65,7 +209,7 @@ pub async fn subscribe(
     .await
     .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(subscriber_id)
 }
 
 #[tracing::instrument(
@@ -104,7 +248,7 @@ async fn send_confirmation_email(
     skip(transaction, subscriber)
 )]
 async fn insert_subscriber(
-    transaction: &mut Transaction<'static, Postgres>,
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
@@ -125,6 +269,39 @@ async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+#[tracing::instrument(
+    name = "Saving subscription consent in the database",
+    skip(transaction, consent)
+)]
+async fn insert_subscription_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent: &SubscriptionConsent,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscription_consents (
+            subscriber_id,
+            source,
+            consent_text_version,
+            source_ip,
+            user_agent,
+            consented_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        subscriber_id,
+        consent.source.as_str(),
+        consent.text_version,
+        consent.ip,
+        consent.user_agent
+    );
+
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
 #[tracing::instrument(name = "Generating subscription token")]
 fn ganerate_subscription_token() -> String {
     let mut rng = thread_rng();
@@ -140,7 +317,7 @@ fn ganerate_subscription_token() -> String {
     skip(subscription_token, transaction)
 )]
 pub async fn store_subscription_token(
-    transaction: &mut Transaction<'static, Postgres>,
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
@@ -182,10 +359,28 @@ impl std::fmt::Display for StoreTokenError {
     }
 }
 
+#[derive(thiserror::Error)]
+pub enum RegisterSubscriberError {
+    #[error("This email address is already subscribed.")]
+    AlreadySubscribed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for RegisterSubscriberError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, formatter)
+    }
+}
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("{0}")]
+    RejectedEmail(EmailRejection),
+    #[error("This email address is already subscribed.")]
+    AlreadySubscribed,
     // Transparent delegates both `Display`'s and `source`'s implementation
     // to the type wrapped by `UnexpectedError`.
     #[error(transparent)]
@@ -201,10 +396,45 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) | SubscribeError::RejectedEmail(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SubscribeError::AlreadySubscribed => StatusCode::CONFLICT,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            SubscribeError::ValidationError(message) => problem.with_detail(message),
+            SubscribeError::RejectedEmail(rejection) => problem
+                .with_detail(rejection.to_string())
+                .with_invalid_params(vec![rejection.into()]),
+            SubscribeError::AlreadySubscribed => problem.with_detail(self.to_string()),
+            SubscribeError::UnexpectedError(_) => problem,
+        }
+        .into_response()
+    }
+}
+
+impl From<RegisterSubscriberError> for SubscribeError {
+    fn from(error: RegisterSubscriberError) -> Self {
+        match error {
+            RegisterSubscriberError::AlreadySubscribed => Self::AlreadySubscribed,
+            RegisterSubscriberError::UnexpectedError(error) => Self::UnexpectedError(error),
+        }
+    }
+}
+
+impl From<&EmailRejection> for InvalidParam {
+    fn from(rejection: &EmailRejection) -> Self {
+        let param = InvalidParam::new("email", rejection.to_string());
+        match rejection.suggestion() {
+            Some(suggestion) => param.with_suggestion(suggestion),
+            None => param,
+        }
+    }
 }
 
 pub fn error_chain_fmt(
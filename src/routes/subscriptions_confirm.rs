@@ -1,11 +1,14 @@
 use actix_web::{
     http::StatusCode,
     web::{self, Data},
-    HttpResponse, ResponseError,
+    HttpRequest, HttpResponse, ResponseError,
 };
-use sqlx::PgPool;
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::client_ip::client_ip;
+use crate::error::Problem;
 use crate::routes::error_chain_fmt;
 
 #[derive(serde::Deserialize)]
@@ -13,37 +16,84 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(pool, parameters))]
-pub async fn confirm(pool: Data<PgPool>, parameters: web::Query<Parameters>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
-        }
-    }
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(pool, parameters, request))]
+pub async fn confirm(
+    pool: Data<PgPool>,
+    parameters: web::Query<Parameters>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ConfirmationError> {
+    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscriber id associated with the provided token.")?
+        .ok_or(ConfirmationError::UnknownToken)?;
+
+    // A confirmed subscriber must never be left without a confirmed consent.
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?;
+
+    let confirmation_ip = client_ip(&request).map(|ip| ip.to_string());
+    record_consent_confirmation(&mut transaction, subscriber_id, confirmation_ip.as_deref())
+        .await
+        .context("Failed to record the consent confirmation.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
         r#"
         UPDATE subscriptions
         SET status = 'confirmed'
         WHERE id = $1
         "#,
         subscriber_id
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Record subscription consent confirmation",
+    skip(transaction, subscriber_id, confirmation_ip)
+)]
+pub async fn record_consent_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    confirmation_ip: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    // Only the first click on the confirmation link is the one that counts.
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscription_consents
+        SET confirmed_at = now(), confirmation_ip = $2
+        WHERE subscriber_id = $1 AND confirmed_at IS NULL
+        "#,
+        subscriber_id,
+        confirmation_ip
+    );
+    transaction.execute(query).await.map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
@@ -94,4 +144,13 @@ impl ResponseError for ConfirmationError {
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            Self::UnknownToken => problem.with_detail(self.to_string()),
+            Self::UnexpectedError(_) => problem,
+        }
+        .into_response()
+    }
 }
@@ -2,25 +2,106 @@ use std::future::{ready, Ready};
 
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::{dev::Payload, FromRequest};
+use chrono::{DateTime, TimeDelta, Utc};
 use uuid::Uuid;
 
+/// How long a user has to provide their second factor after entering the right password.
+pub const PENDING_LOGIN_VALIDITY: TimeDelta = TimeDelta::minutes(5);
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const PENDING_SINCE_KEY: &'static str = "pending_since";
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
     }
 
-    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
-        self.0.insert(Self::USER_ID_KEY, user_id)
+    /// Log the user in, remembering when and under which id the session is tracked
+    /// so that it can be revoked later on.
+    pub fn insert_user_id(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<(), SessionInsertError> {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+        self.0.remove(Self::PENDING_SINCE_KEY);
+        // Forms rendered before logging in must not be accepted afterwards.
+        self.0.remove(Self::CSRF_TOKEN_KEY);
+        self.0.insert(Self::USER_ID_KEY, user_id)?;
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
+        self.0
+            .insert(Self::LOGGED_IN_AT_KEY, Utc::now().timestamp_micros())
     }
 
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn get_logged_in_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<i64>(Self::LOGGED_IN_AT_KEY)?
+            .and_then(DateTime::from_timestamp_micros))
+    }
+
+    /// Remember a user who entered the right password but still has to provide a second factor.
+    pub fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)?;
+        self.0
+            .insert(Self::PENDING_SINCE_KEY, Utc::now().timestamp_micros())
+    }
+
+    /// The user halfway through logging in, for [`PENDING_LOGIN_VALIDITY`] at most:
+    /// after that they have to enter their password again.
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        let pending_since = self
+            .0
+            .get::<i64>(Self::PENDING_SINCE_KEY)?
+            .and_then(DateTime::from_timestamp_micros);
+        match pending_since {
+            Some(since) if since + PENDING_LOGIN_VALIDITY > Utc::now() => {
+                self.0.get(Self::PENDING_USER_ID_KEY)
+            }
+            _ => {
+                self.0.remove(Self::PENDING_USER_ID_KEY);
+                self.0.remove(Self::PENDING_SINCE_KEY);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Hold on to a TOTP secret until the user proves their authenticator app has enrolled it.
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
@@ -1,9 +1,9 @@
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
-use actix_web::cookie::Key;
+use actix_web::cookie::{Key, SameSite};
 use actix_web::dev::Server;
 use actix_web::middleware::from_fn;
-use actix_web::web::{get, post, scope, Data};
+use actix_web::web::{get, post, resource, scope, Data};
 use actix_web::{App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
@@ -12,14 +12,28 @@ use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::io::Error;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger; // Transmission Control Protocol: [TCP]
 
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
+use crate::authentication::{
+    reject_anonymous_api_clients, reject_anonymous_users, reject_forged_requests, require_role,
+    LoginLockout, PasswordHashing, Role,
+};
+use crate::configuration::{DatabaseSettings, RateLimitBackend, Settings};
+use crate::domain::{EmailScreening, PasswordPolicy};
+use crate::error::render_problem_details;
+use crate::idempotency::{enforce_idempotency, IdempotencyPolicy};
+use crate::mail_domain::MailDomainResolver;
+use crate::rate_limit::{enforce_rate_limit, RateLimitPolicy, RateLimiter};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    accept_invitation, accept_invitation_form, admin_dashboard, api_json_config,
+    api_publish_newsletter, api_subscribe, api_tokens_form, change_email, change_password,
+    change_password_form, confirm, create_token, email_form, enroll_two_factor, forgot_password,
+    forgot_password_form, health_check, home, invitations_form, invite_user, list_sessions,
+    list_subscribers, log_out, login, login_form, login_two_factor, login_two_factor_form,
+    publish_newsletter, publish_newsletter_form, reset_password, reset_password_form,
+    revoke_all_other_sessions, revoke_one_session, revoke_token, subscribe, subscriber_details,
+    turn_off_two_factor, two_factor_form, SubscriptionFormGuard, NEWSLETTER_PUBLISHED,
 };
 
 // NOTE: HTTP & TCP is a protocol
@@ -39,76 +53,262 @@ impl Application {
         config: Settings,
         connection_pool: PgPool,
     ) -> Result<Application, anyhow::Error> {
-        let email_client = config.email_client.client();
+        Self::build_inner(config, connection_pool, None).await
+    }
 
+    /// Build the application with a custom way of checking subscriber email domains.
+    pub async fn build_with_resolver(
+        config: Settings,
+        connection_pool: PgPool,
+        resolver: Arc<dyn MailDomainResolver>,
+    ) -> Result<Application, anyhow::Error> {
+        Self::build_inner(config, connection_pool, Some(resolver)).await
+    }
+
+    async fn build_inner(
+        config: Settings,
+        connection_pool: PgPool,
+        resolver: Option<Arc<dyn MailDomainResolver>>,
+    ) -> Result<Application, anyhow::Error> {
         let address = format!("{}:{}", config.application.host, config.application.port);
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
 
-        let server = Self::run(
-            listener,
-            connection_pool,
-            email_client,
-            config.application.base_url,
-            config.application.hmac_secret,
-            config.redis_uri,
-        )
-        .await?;
+        let server = Self::run(listener, connection_pool, config, resolver).await?;
 
         Ok(Self { port, server })
     }
 
-    pub fn db_connection_pool(configuration: &DatabaseSettings) -> Result<PgPool, sqlx::Error> {
-        PgPoolOptions::new().connect_lazy(&configuration.connection_string())
+    pub fn db_connection_pool(configuration: &DatabaseSettings) -> PgPool {
+        PgPoolOptions::new().connect_lazy_with(configuration.connect_options())
     }
 
     async fn run(
         listener: TcpListener,
         db_pool: PgPool,
-        email_client: EmailClient,
-        base_url: String,
-        hmac_secret: Secret<String>,
-        redis_uri: Secret<String>,
+        config: Settings,
+        resolver: Option<Arc<dyn MailDomainResolver>>,
     ) -> Result<Server, anyhow::Error> {
         let db_pool = Data::new(db_pool);
-        let email_client = Data::new(email_client);
-        let base_url = Data::new(ApplicationBaseUrl(base_url));
-        let hmac_secret = HmacSecret(hmac_secret);
+        let email_client = Data::new(config.email_client.client());
+        let trusted_proxies = Data::new(
+            config
+                .application
+                .trusted_proxies()
+                .map_err(anyhow::Error::msg)?,
+        );
+        let base_url = Data::new(ApplicationBaseUrl(config.application.base_url));
+        let hmac_secret = Data::new(HmacSecret(config.application.hmac_secret));
+        let password_policy = Data::new(PasswordPolicy::from_settings(&config.password_policy)?);
+        let password_hashing = Data::new(PasswordHashing::new(&config.password_hashing)?);
+        let login_lockout = Data::new(LoginLockout::new(config.login_lockout));
+        let subscription_form_guard = Data::new(SubscriptionFormGuard::new(
+            hmac_secret.get_ref().clone(),
+            config.subscription_form,
+        ));
+        let email_screening = Data::new(EmailScreening::from_settings(
+            &config.email_screening,
+            resolver,
+        )?);
 
         let secret_key = Key::from(hmac_secret.0.expose_secret().as_bytes());
         let message_store = CookieMessageStore::builder(secret_key.clone()).build();
         let message_framework = FlashMessagesFramework::builder(message_store).build();
 
-        let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+        let redis_store = RedisSessionStore::new(config.redis_uri.expose_secret()).await?;
+
+        let rate_limiter = Data::new(match config.rate_limit.backend {
+            RateLimitBackend::Redis => RateLimiter::redis(config.redis_uri.expose_secret())?,
+            RateLimitBackend::InMemory => RateLimiter::in_memory(),
+        });
+        let subscriptions_limit =
+            RateLimitPolicy::new("subscriptions", config.rate_limit.subscriptions)
+                .keyed_on("email");
+        let login_limit =
+            RateLimitPolicy::new("login", config.rate_limit.login.clone()).keyed_on("username");
+        let confirm_limit = RateLimitPolicy::new("confirm", config.rate_limit.confirm);
+        // Codes are only six digits long: guessing them must be as slow as guessing passwords.
+        let two_factor_limit =
+            RateLimitPolicy::new("two_factor", config.rate_limit.login).keyed_on_pending_user();
+        let password_reset_limit =
+            RateLimitPolicy::new("password_reset", config.rate_limit.password_reset)
+                .keyed_on("email");
+
+        let idempotency = &config.idempotency;
+        let concurrent_requests = idempotency.concurrent_requests;
+        let subscription_form_idempotency =
+            IdempotencyPolicy::new(idempotency, concurrent_requests.subscription_form).optional();
+        let subscription_api_idempotency =
+            IdempotencyPolicy::new(idempotency, concurrent_requests.subscription_api).optional();
+        let newsletter_form_idempotency =
+            IdempotencyPolicy::new(idempotency, concurrent_requests.newsletter_form)
+                .for_form("/admin/newsletters")
+                .replaying(NEWSLETTER_PUBLISHED);
+        let newsletter_api_idempotency =
+            IdempotencyPolicy::new(idempotency, concurrent_requests.newsletter_api);
+        // Passwords must not be stored, not even as part of a fingerprint.
+        let password_form_idempotency =
+            IdempotencyPolicy::new(idempotency, concurrent_requests.password_form)
+                .for_form("/admin/password")
+                .without_fingerprinting(&["current_password", "new_password", "new_password_check"])
+                .optional();
 
         let server = HttpServer::new(move || {
+            let subscriptions_limit = subscriptions_limit.clone();
+            let api_subscriptions_limit = subscriptions_limit.clone();
+            let login_limit = login_limit.clone();
+            let confirm_limit = confirm_limit.clone();
+            let two_factor_limit = two_factor_limit.clone();
+            let password_reset_limit = password_reset_limit.clone();
+
             App::new()
+                .wrap(from_fn(render_problem_details))
                 .wrap(TracingLogger::default())
                 .wrap(message_framework.clone())
-                .wrap(SessionMiddleware::new(
-                    redis_store.clone(),
-                    secret_key.clone(),
-                ))
+                .wrap(
+                    // Browsers leave the session cookie out of cross-site requests altogether.
+                    SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                        .cookie_same_site(SameSite::Strict)
+                        .build(),
+                )
                 .route("/", get().to(home))
                 .route("/login", get().to(login_form))
-                .route("/login", post().to(login))
+                .route(
+                    "/login",
+                    post().to(login).wrap(from_fn(move |req, next| {
+                        enforce_rate_limit(login_limit.clone(), req, next)
+                    })),
+                )
+                .route("/login/two-factor", get().to(login_two_factor_form))
+                .route(
+                    "/login/two-factor",
+                    post().to(login_two_factor).wrap(from_fn(move |req, next| {
+                        enforce_rate_limit(two_factor_limit.clone(), req, next)
+                    })),
+                )
+                .route("/login/forgot", get().to(forgot_password_form))
+                .route(
+                    "/login/forgot",
+                    post().to(forgot_password).wrap(from_fn(move |req, next| {
+                        enforce_rate_limit(password_reset_limit.clone(), req, next)
+                    })),
+                )
+                .route("/login/reset", get().to(reset_password_form))
+                .route("/login/reset", post().to(reset_password))
                 .route("/health-check", get().to(health_check))
-                .route("/subscriptions", post().to(subscribe))
-                .route("/subscriptions/confirm", get().to(confirm))
-                .route("/newsletters", post().to(publish_newsletter))
+                .route(
+                    "/subscriptions",
+                    post()
+                        .to(subscribe)
+                        .wrap(from_fn(move |req, next| {
+                            enforce_idempotency(subscription_form_idempotency, req, next)
+                        }))
+                        .wrap(from_fn(move |req, next| {
+                            enforce_rate_limit(subscriptions_limit.clone(), req, next)
+                        })),
+                )
+                .route(
+                    "/subscriptions/confirm",
+                    get().to(confirm).wrap(from_fn(move |req, next| {
+                        enforce_rate_limit(confirm_limit.clone(), req, next)
+                    })),
+                )
+                .route("/invitations/accept", get().to(accept_invitation_form))
+                .route("/invitations/accept", post().to(accept_invitation))
+                .service(
+                    scope("/api/v1")
+                        .service(
+                            resource("/subscriptions")
+                                .app_data(api_json_config())
+                                .route(
+                                    post()
+                                        .to(api_subscribe)
+                                        .wrap(from_fn(move |req, next| {
+                                            enforce_idempotency(
+                                                subscription_api_idempotency,
+                                                req,
+                                                next,
+                                            )
+                                        }))
+                                        .wrap(from_fn(move |req, next| {
+                                            enforce_rate_limit(
+                                                api_subscriptions_limit.clone(),
+                                                req,
+                                                next,
+                                            )
+                                        })),
+                                ),
+                        )
+                        .route(
+                            "/newsletters",
+                            post()
+                                .to(api_publish_newsletter)
+                                .wrap(from_fn(move |req, next| {
+                                    enforce_idempotency(newsletter_api_idempotency, req, next)
+                                }))
+                                .wrap(from_fn(reject_anonymous_api_clients)),
+                        ),
+                )
                 .service(
                     scope("/admin")
+                        .wrap(from_fn(reject_forged_requests))
                         .wrap(from_fn(reject_anonymous_users))
                         .route("/dashboard", get().to(admin_dashboard))
                         .route("/newsletters", get().to(publish_newsletter_form))
-                        .route("/newsletters", post().to(publish_newsletter))
+                        .route(
+                            "/newsletters",
+                            post()
+                                .to(publish_newsletter)
+                                .wrap(from_fn(move |req, next| {
+                                    enforce_idempotency(newsletter_form_idempotency, req, next)
+                                }))
+                                .wrap(from_fn(|req, next| require_role(Role::Editor, req, next))),
+                        )
                         .route("/password", get().to(change_password_form))
-                        .route("/password", post().to(change_password))
+                        .route(
+                            "/password",
+                            post().to(change_password).wrap(from_fn(move |req, next| {
+                                enforce_idempotency(password_form_idempotency, req, next)
+                            })),
+                        )
+                        .route("/password/email", get().to(email_form))
+                        .route("/password/email", post().to(change_email))
+                        .route("/password/two-factor", get().to(two_factor_form))
+                        .route("/password/two-factor", post().to(enroll_two_factor))
+                        .route(
+                            "/password/two-factor/disable",
+                            post().to(turn_off_two_factor),
+                        )
+                        .service(
+                            resource("/invitations")
+                                .wrap(from_fn(|req, next| require_role(Role::Owner, req, next)))
+                                .route(get().to(invitations_form))
+                                .route(post().to(invite_user)),
+                        )
+                        .route("/api-tokens", get().to(api_tokens_form))
+                        .route("/api-tokens", post().to(create_token))
+                        .route("/api-tokens/revoke", post().to(revoke_token))
+                        .route("/sessions", get().to(list_sessions))
+                        .route("/sessions/revoke", post().to(revoke_one_session))
+                        .route(
+                            "/sessions/revoke-others",
+                            post().to(revoke_all_other_sessions),
+                        )
+                        .route("/subscribers", get().to(list_subscribers))
+                        .route("/subscribers/{subscriber_id}", get().to(subscriber_details))
                         .route("/logout", post().to(log_out)),
                 )
                 .app_data(db_pool.clone())
+                .app_data(trusted_proxies.clone())
                 .app_data(email_client.clone())
                 .app_data(base_url.clone())
+                .app_data(rate_limiter.clone())
+                .app_data(hmac_secret.clone())
+                .app_data(subscription_form_guard.clone())
+                .app_data(login_lockout.clone())
+                .app_data(password_policy.clone())
+                .app_data(password_hashing.clone())
+                .app_data(email_screening.clone())
         })
         .listen(listener)?
         .run();
@@ -0,0 +1,213 @@
+//! src/templates/admin.rs
+
+use askama::Template;
+use uuid::Uuid;
+
+use super::AdminNav;
+use crate::authentication::{ActiveSession, ApiScope, ApiTokenSummary, FailedLoginAttempt, Role};
+use crate::routes::{PendingInvitation, SubscriberDetails, SubscriberSummary};
+
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+pub struct DashboardPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub username: String,
+    pub failed_logins: Vec<FailedLoginAttempt>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+pub struct ChangePasswordPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub idempotency_key: Uuid,
+}
+
+/// `email` is where password reset links are sent, if the user has set one.
+#[derive(Template)]
+#[template(path = "admin/email.html")]
+pub struct EmailPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub email: Option<String>,
+}
+
+/// What a user needs to add a pending TOTP secret to their authenticator app.
+pub struct TwoFactorEnrollment {
+    pub otpauth_uri: String,
+    pub secret: String,
+}
+
+/// `enrollment` is `None` once two-factor authentication is enabled.
+#[derive(Template)]
+#[template(path = "admin/two_factor.html")]
+pub struct TwoFactorPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub enrollment: Option<TwoFactorEnrollment>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/recovery_codes.html")]
+pub struct RecoveryCodesPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter.html")]
+pub struct PublishNewsletterPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub idempotency_key: Uuid,
+}
+
+#[derive(Template)]
+#[template(path = "admin/invitations.html")]
+pub struct InvitationsPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub roles: [Role; 3],
+    pub pending_invitations: Vec<PendingInvitation>,
+}
+
+impl InvitationsPage {
+    /// The role preselected in the form: enough to help out, not enough to invite others.
+    fn is_default_role(&self, role: &Role) -> bool {
+        *role == Role::Editor
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/sessions.html")]
+pub struct SessionsPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub sessions: Vec<ActiveSession>,
+    pub current_session_id: Option<Uuid>,
+}
+
+impl SessionsPage {
+    fn is_current(&self, session: &ActiveSession) -> bool {
+        Some(session.session_id) == self.current_session_id
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/api_tokens.html")]
+pub struct ApiTokensPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub tokens: Vec<ApiTokenSummary>,
+    pub scopes: Vec<ApiScope>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/api_token_created.html")]
+pub struct ApiTokenCreatedPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub name: String,
+    pub token: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+pub struct SubscribersPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub subscribers: Vec<SubscriberSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscriber.html")]
+pub struct SubscriberPage {
+    pub nav: AdminNav,
+    pub messages: Vec<String>,
+    pub subscriber: SubscriberDetails,
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{DashboardPage, SessionsPage};
+    use crate::authentication::{ActiveSession, FailedLoginAttempt, Role};
+    use crate::templates::tests::nav;
+
+    fn dashboard(role: Role) -> DashboardPage {
+        DashboardPage {
+            nav: nav(role),
+            messages: vec![],
+            username: "ursula".into(),
+            failed_logins: vec![],
+        }
+    }
+
+    fn session(user_agent: &str) -> ActiveSession {
+        ActiveSession {
+            session_id: Uuid::new_v4(),
+            user_agent: Some(user_agent.into()),
+            ip: None,
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn the_admin_navigation_carries_a_logout_form_with_the_csrf_token() {
+        let html = dashboard(Role::Viewer).render().unwrap();
+
+        assert!(html.contains(r#"<form name="logoutForm" action="/admin/logout" method="post">"#));
+        assert!(html.contains(r#"<input type="hidden" name="csrf_token" value="abc123">"#));
+    }
+
+    #[test]
+    fn links_are_only_shown_to_the_roles_that_can_follow_them() {
+        let viewer = dashboard(Role::Viewer).render().unwrap();
+        let owner = dashboard(Role::Owner).render().unwrap();
+
+        assert!(!viewer.contains(r#"href="/admin/newsletters""#));
+        assert!(!viewer.contains(r#"href="/admin/invitations""#));
+        assert!(owner.contains(r#"href="/admin/newsletters""#));
+        assert!(owner.contains(r#"href="/admin/invitations""#));
+    }
+
+    #[test]
+    fn usernames_of_failed_logins_are_escaped() {
+        let mut page = dashboard(Role::Owner);
+        page.failed_logins.push(FailedLoginAttempt {
+            username: "<img src=x onerror=alert(1)>".into(),
+            ip: None,
+            outcome: "unknown_username".into(),
+            attempted_at: Utc::now(),
+        });
+
+        let html = page.render().unwrap();
+
+        assert!(!html.contains("<img"));
+        assert!(html.contains("<td>unknown</td><td>unknown username</td>"));
+    }
+
+    #[test]
+    fn only_other_sessions_can_be_revoked() {
+        let current = session("Laptop Browser/1.0");
+        let other = session("<script>Phone</script>");
+        let page = SessionsPage {
+            nav: nav(Role::Viewer),
+            messages: vec![],
+            current_session_id: Some(current.session_id),
+            sessions: vec![current, other],
+        };
+
+        let html = page.render().unwrap();
+
+        assert_eq!(html.matches("This session").count(), 1);
+        assert_eq!(html.matches(r#"name="session_id""#).count(), 1);
+        assert!(html.contains("&#60;script&#62;Phone&#60;/script&#62;"));
+    }
+}
@@ -0,0 +1,128 @@
+//! src/templates/mod.rs
+//!
+//! The server-rendered pages. Each page is a struct checked against its template in
+//! `templates/` at compile time, and every value is HTML-escaped unless the template
+//! explicitly marks it as `safe`.
+
+mod admin;
+mod public;
+
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::http::header::ContentType;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+
+use crate::authentication::{CsrfToken, Role};
+use crate::utils::error_500;
+
+pub use admin::*;
+pub use public::*;
+
+/// Render `page` as the body of a `200 OK` HTML response.
+pub fn render(page: &impl Template) -> Result<HttpResponse, askama::Error> {
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page.render()?))
+}
+
+/// The contents of the flash messages of a request, to be shown above the page.
+pub fn read_flash_messages(flash_messages: &IncomingFlashMessages) -> Vec<String> {
+    flash_messages
+        .iter()
+        .map(|message| message.content().to_owned())
+        .collect()
+}
+
+/// What the navigation bar of the admin area needs to know about the current request.
+///
+/// It is only available behind `reject_anonymous_users` and `reject_forged_requests`,
+/// which store the role of the user and the CSRF token of the session.
+#[derive(Debug, Clone)]
+pub struct AdminNav {
+    role: Role,
+    csrf_token: CsrfToken,
+}
+
+impl AdminNav {
+    pub fn new(role: Role, csrf_token: CsrfToken) -> Self {
+        Self { role, csrf_token }
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn csrf_field(&self) -> String {
+        self.csrf_token.form_field()
+    }
+
+    pub fn can_publish(&self) -> bool {
+        self.role.allows(Role::Editor)
+    }
+
+    pub fn can_invite(&self) -> bool {
+        self.role.allows(Role::Owner)
+    }
+}
+
+impl FromRequest for AdminNav {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<AdminNav, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        let nav = match (extensions.get::<Role>(), extensions.get::<CsrfToken>()) {
+            (Some(role), Some(csrf_token)) => Ok(AdminNav::new(*role, csrf_token.clone())),
+            _ => Err(error_500(
+                "The admin navigation is only available behind the admin middleware",
+            )),
+        };
+        ready(nav)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::{AdminNav, HomePage, LoginPage};
+    use crate::authentication::{CsrfToken, Role};
+
+    pub(super) fn nav(role: Role) -> AdminNav {
+        AdminNav::new(role, CsrfToken("abc123".into()))
+    }
+
+    #[test]
+    fn flash_messages_are_escaped() {
+        let page = LoginPage {
+            messages: vec!["<script>alert('pwned')</script>".into()],
+        };
+
+        let html = page.render().unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(
+            html.contains("<p><i>&#60;script&#62;alert(&#39;pwned&#39;)&#60;/script&#62;</i></p>")
+        );
+    }
+
+    #[test]
+    fn public_pages_link_to_the_home_page_and_the_login_form() {
+        let html = HomePage::new("1700000000.cafe".into()).render().unwrap();
+
+        assert!(html.contains(r#"<a href="/">Home</a>"#));
+        assert!(html.contains(r#"<a href="/login">Log in</a>"#));
+        assert!(!html.contains("/admin/logout"));
+    }
+
+    #[test]
+    fn the_admin_navigation_follows_the_role_of_the_user() {
+        assert!(!nav(Role::Viewer).can_publish());
+        assert!(nav(Role::Editor).can_publish());
+        assert!(!nav(Role::Editor).can_invite());
+        assert!(nav(Role::Owner).can_invite());
+    }
+}
@@ -0,0 +1,142 @@
+//! src/templates/public.rs
+
+use askama::Template;
+use uuid::Uuid;
+
+use crate::domain::{CONSENT_TEXT, CONSENT_TEXT_VERSION};
+
+#[derive(Template)]
+#[template(path = "home.html")]
+pub struct HomePage {
+    pub messages: Vec<String>,
+    pub form_token: String,
+    pub idempotency_key: Uuid,
+    pub consent_text: &'static str,
+    pub consent_text_version: &'static str,
+    pub name: String,
+    pub email: String,
+    /// Offer to subscribe `email` as typed, after it was flagged as a likely typo.
+    pub ask_to_keep_email: bool,
+}
+
+impl HomePage {
+    /// An empty subscription form, protected by `form_token`.
+    pub fn new(form_token: String) -> Self {
+        Self {
+            messages: Vec::new(),
+            form_token,
+            idempotency_key: Uuid::new_v4(),
+            consent_text: CONSENT_TEXT,
+            consent_text_version: CONSENT_TEXT_VERSION,
+            name: String::new(),
+            email: String::new(),
+            ask_to_keep_email: false,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "login/login.html")]
+pub struct LoginPage {
+    pub messages: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login/two_factor.html")]
+pub struct LoginTwoFactorPage {
+    pub messages: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login/forgot.html")]
+pub struct ForgotPasswordPage {
+    pub messages: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login/reset.html")]
+pub struct ResetPasswordPage {
+    pub messages: Vec<String>,
+    pub reset_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "accept_invitation.html")]
+pub struct AcceptInvitationPage {
+    pub messages: Vec<String>,
+    pub email: String,
+    pub invitation_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::{AcceptInvitationPage, HomePage, ResetPasswordPage};
+
+    #[test]
+    fn the_home_page_embeds_the_form_token() {
+        let html = HomePage::new("1700000000.cafe".into()).render().unwrap();
+
+        assert!(html.contains(r#"<input type="hidden" name="form_token" value="1700000000.cafe">"#));
+    }
+
+    #[test]
+    fn the_home_page_shows_the_versioned_consent_text() {
+        let html = HomePage {
+            consent_text: "By subscribing you agree.",
+            consent_text_version: "2024-11-01",
+            ..HomePage::new("1700000000.cafe".into())
+        }
+        .render()
+        .unwrap();
+
+        assert!(html.contains(
+            r#"<p class="consent" data-version="2024-11-01">By subscribing you agree.</p>"#
+        ));
+    }
+
+    #[test]
+    fn the_home_page_offers_to_keep_an_address_that_looks_mistyped() {
+        let page = HomePage::new("1700000000.cafe".into());
+        assert!(!page.render().unwrap().contains(r#"name="keep_email""#));
+
+        let html = HomePage {
+            name: "Ursula".into(),
+            email: "ursula@gmial.com".into(),
+            ask_to_keep_email: true,
+            ..page
+        }
+        .render()
+        .unwrap();
+
+        assert!(html.contains(r#"name="email" value="ursula@gmial.com""#));
+        assert!(html.contains(r#"<input type="checkbox" name="keep_email" value="true">"#));
+    }
+
+    #[test]
+    fn tokens_from_the_query_string_cannot_break_out_of_their_attribute() {
+        let html = ResetPasswordPage {
+            messages: vec![],
+            reset_token: r#""><script>"#.into(),
+        }
+        .render()
+        .unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(r#"name="reset_token" value="&#34;&#62;&#60;script&#62;""#));
+    }
+
+    #[test]
+    fn the_invitation_form_escapes_the_invited_email() {
+        let html = AcceptInvitationPage {
+            messages: vec![],
+            email: "<b>ursula</b>@example.com".into(),
+            invitation_token: "token".into(),
+        }
+        .render()
+        .unwrap();
+
+        assert!(html.contains("Create the account for &#60;b&#62;ursula&#60;/b&#62;@example.com."));
+    }
+}
@@ -1,15 +1,23 @@
 use std::fmt::{Debug, Display};
 
-use actix_web::error::ErrorInternalServerError;
-use actix_web::http::header::LOCATION;
+use actix_http::h1;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{CONTENT_TYPE, LOCATION};
+use actix_web::web::Bytes;
 use actix_web::HttpResponse;
 
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+
 // Return an opaque 500 while preserving the error root's cause for logging.
 pub fn error_500<T>(error: T) -> actix_web::Error
 where
-    T: Debug + Display + 'static,
+    T: Debug + Display + Send + Sync + 'static,
 {
-    ErrorInternalServerError(error)
+    AppError::internal(anyhow::anyhow!(error)).into()
 }
 
 pub fn see_other(location: &str) -> HttpResponse {
@@ -22,7 +30,93 @@ pub fn see_other(location: &str) -> HttpResponse {
 // The error root cause is preserved for logging purposes.
 pub fn error_400<T>(e: T) -> actix_web::Error
 where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
+    T: Debug + Display + Send + Sync + 'static,
 {
-    actix_web::error::ErrorBadRequest(e)
+    AppError::bad_request(anyhow::anyhow!(e)).into()
+}
+
+/// A random alphanumeric token, suitable for single-use links sent by email.
+pub fn generate_token(length: usize) -> String {
+    let mut rng = thread_rng();
+
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+/// The digest under which a token from `generate_token` is stored, so that reading the
+/// database is not enough to use it. Tokens carry enough entropy for a fast hash.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The unique constraint that made a query fail, if that is what happened.
+pub fn violated_unique_constraint(error: &anyhow::Error) -> Option<&str> {
+    error
+        .downcast_ref::<sqlx::Error>()?
+        .as_database_error()
+        .filter(|error| error.is_unique_violation())?
+        .constraint()
+}
+
+/// Compare secrets in constant time, so that response times do not leak how much matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Read `field` from a form or JSON body in a middleware, putting the body back for the handler.
+pub async fn read_body_field(
+    req: &mut ServiceRequest,
+    field: &str,
+) -> Result<Option<String>, actix_web::Error> {
+    Ok(read_body(req)
+        .await?
+        .and_then(|body| body.get(field)?.as_str().map(ToOwned::to_owned)))
+}
+
+/// Read a form or JSON body in a middleware as JSON, putting the body back for the handler.
+///
+/// Repeated form fields, such as checkboxes, are read as arrays. Bodies that cannot be
+/// parsed are left for the handler to reject.
+pub async fn read_body(
+    req: &mut ServiceRequest,
+) -> Result<Option<serde_json::Value>, actix_web::Error> {
+    let body = req.extract::<Bytes>().await?;
+    let (_, mut payload) = h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    req.set_payload(payload.into());
+
+    let is_json = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.contains("json"))
+        .unwrap_or(false);
+
+    let value = if is_json {
+        serde_json::from_slice(&body).ok()
+    } else {
+        serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+            .ok()
+            .map(form_to_json)
+    };
+
+    Ok(value)
+}
+
+fn form_to_json(fields: Vec<(String, String)>) -> serde_json::Value {
+    let mut object = serde_json::Map::new();
+
+    for (name, value) in fields {
+        match object.get_mut(&name) {
+            Some(serde_json::Value::Array(values)) => values.push(value.into()),
+            Some(first) => *first = vec![first.take(), value.into()].into(),
+            None => {
+                object.insert(name, value.into());
+            }
+        }
+    }
+
+    object.into()
 }
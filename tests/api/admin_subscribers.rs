@@ -0,0 +1,78 @@
+//! tests/api/admin_subscribers.rs
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app.get_subscribers().await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscriber_details_show_the_recorded_consent() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.test_user.login(&app).await;
+
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Act - Part 1 - The subscriber list links to the details page
+    let html_page = app.get_subscribers().await.text().await.unwrap();
+    assert!(html_page.contains(&format!("/admin/subscribers/{}", subscriber_id)));
+
+    // Act - Part 2 - Load the details page
+    let html_page = app
+        .get_subscriber_details(&subscriber_id.to_string())
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains("<li>Source: form</li>"));
+    assert!(html_page.contains(&format!(
+        "<li>Consent text version: {}</li>",
+        zero_to_prod::domain::CONSENT_TEXT_VERSION
+    )));
+    assert!(html_page.contains("<li>Source IP: 127.0.0.1</li>"));
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_a_404() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_subscriber_details(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,227 @@
+//! tests/api/api_subscriptions.rs
+
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn api_subscribe_returns_a_201_with_the_created_subscription() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.id, s.status, c.source
+        FROM subscriptions s
+        JOIN subscription_consents c ON c.subscriber_id = s.id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+
+    assert_eq!(body["id"], saved.id.to_string());
+    assert_eq!(body["status"], "pending_confirmation");
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(saved.source, "api");
+}
+
+#[tokio::test]
+async fn api_subscribe_reports_which_fields_are_invalid() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        (
+            json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            vec!["name"],
+        ),
+        (
+            json!({"name": "Ursula", "email": "not-an-email"}),
+            vec!["email"],
+        ),
+        (json!({"name": "", "email": ""}), vec!["name", "email"]),
+    ];
+
+    for (body, invalid_fields) in test_cases {
+        // Act
+        let response = app.post_api_subscriptions(&body).await;
+
+        // Assert
+        assert_eq!(400, response.status().as_u16());
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["type"], "/problems/validation-error");
+
+        let fields: Vec<&str> = error["invalid_params"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, invalid_fields, "Unexpected errors for {}", body);
+    }
+}
+
+#[tokio::test]
+async fn api_subscribe_returns_suggestions_for_mistyped_domains() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({"name": "Ursula", "email": "ursula@hotmial.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["invalid_params"][0]["name"], "email");
+    assert_eq!(
+        error["invalid_params"][0]["suggestion"],
+        "ursula@hotmail.com"
+    );
+}
+
+#[tokio::test]
+async fn api_subscribe_accepts_a_mistyped_looking_domain_the_client_chose_to_keep() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({
+            "name": "Ursula",
+            "email": "ursula@hotmial.com",
+            "keep_email": true
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_subscribe_rejects_disposable_addresses() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({"name": "Ursula", "email": "ursula@yopmail.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["invalid_params"][0]["name"], "email");
+    assert!(error["invalid_params"][0].get("suggestion").is_none());
+}
+
+#[tokio::test]
+async fn api_subscribe_rejects_domains_that_do_not_accept_mail() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({"name": "Ursula", "email": "ursula@nowhere.invalid"}))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["invalid_params"][0]["name"], "email");
+    assert_eq!(
+        error["invalid_params"][0]["reason"],
+        "nowhere.invalid does not accept email."
+    );
+}
+
+#[tokio::test]
+async fn api_subscribe_returns_a_json_400_when_data_is_missing() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let test_cases = vec![
+        (json!({"name": "le guin"}), "missing the email"),
+        (
+            json!({"email": "ursula_le_guin@gmail.com"}),
+            "missing the name",
+        ),
+        (json!({}), "missing both name and email"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_api_subscriptions(&body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            description
+        );
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["type"], "/problems/malformed-body");
+    }
+}
+
+#[tokio::test]
+async fn api_subscribe_returns_a_409_for_an_email_that_is_already_subscribed() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"});
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_api_subscriptions(&body).await;
+    assert_eq!(201, response.status().as_u16());
+
+    // Act
+    let response = app.post_api_subscriptions(&body).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["type"], "/problems/already-subscribed");
+}
@@ -0,0 +1,275 @@
+use serde_json::json;
+use uuid::Uuid;
+use zero_to_prod::authentication::Role;
+
+use crate::helpers::TestApp;
+use crate::test_user::TestUser;
+
+fn issue() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn issue_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_new_token_is_shown_once_and_stored_hashed() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+
+    // Assert
+    assert!(token.starts_with("nlt_"));
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<td>ci</td>"));
+    assert!(html_page.contains("newsletters:publish"));
+    assert!(!html_page.contains(&token));
+
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn tokens_need_a_scope() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_admin("/admin/api-tokens")
+        .await
+        .form(&[("name", "ci")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>Please choose at least one scope.</i></p>"));
+}
+
+#[tokio::test]
+async fn unknown_scopes_are_echoed_back_escaped() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_admin("/admin/api-tokens")
+        .await
+        .form(&[("name", "ci"), ("scope", "<script>alert(1)</script>")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&#60;script&#62;alert(1)&#60;/script&#62; is not a known scope."));
+}
+
+#[tokio::test]
+async fn a_token_can_publish_a_newsletter_issue() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("first-issue"), &issue())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "queued");
+    let issue_id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+    let stored = sqlx::query!(
+        "SELECT title FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.title, "Newsletter title");
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_is_idempotent() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+
+    // Act
+    let first = app
+        .post_api_newsletter(Some(&token), Some("retried-issue"), &issue())
+        .await;
+    let second = app
+        .post_api_newsletter(Some(&token), Some("retried-issue"), &issue())
+        .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    assert_eq!(issue_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    for token in [None, Some("nlt_not-a-real-token")] {
+        // Act
+        let response = app
+            .post_api_newsletter(token, Some("an-issue"), &issue())
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    }
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn tokens_are_checked_before_idempotency_keys() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app.post_api_newsletter(None, None, &issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let claimed = sqlx::query!(r#"SELECT count(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(claimed.count, 0);
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    // Act
+    let response = app
+        .post_revoke_api_token(&json!({ "token_id": token_id }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/api-tokens");
+
+    // Assert
+    let response = app
+        .post_api_newsletter(Some(&token), Some("an-issue"), &issue())
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(!app.get_api_tokens_html().await.contains("<td>ci</td>"));
+}
+
+#[tokio::test]
+async fn tokens_cannot_grant_scopes_beyond_the_role_of_their_owner() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let viewer = TestUser::generate_with_role(Role::Viewer);
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+    assert!(!app
+        .get_api_tokens_html()
+        .await
+        .contains("newsletters:publish"));
+
+    // Act
+    let response = app
+        .post_admin("/admin/api-tokens")
+        .await
+        .form(&[("name", "ci"), ("scope", "newsletters:publish")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app.get_api_tokens_html().await.contains(
+        "<p><i>Your role does not allow granting the newsletters:publish scope.</i></p>"
+    ));
+    let stored = sqlx::query!(r#"SELECT count(*) AS "count!" FROM api_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 0);
+}
+
+#[tokio::test]
+async fn tokens_cannot_do_more_than_their_owner() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    // The owner of the token was demoted after creating it.
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        editor.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("an-issue"), &issue())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn an_idempotency_key_is_required() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+
+    // Act
+    let response = app.post_api_newsletter(Some(&token), None, &issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(issue_count(&app).await, 0);
+}
@@ -0,0 +1,135 @@
+//! tests/api/authorization.rs
+
+use serde_json::json;
+use zero_to_prod::authentication::Role;
+
+use crate::helpers::TestApp;
+use crate::test_user::TestUser;
+
+async fn log_in_as(app: &TestApp, role: Role) -> TestUser {
+    let user = TestUser::generate_with_role(role);
+    user.store(&app.db_pool).await;
+    user.login(app).await;
+    user
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn viewers_can_read_the_dashboard_and_subscribers() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    log_in_as(&app, Role::Viewer).await;
+
+    // Act
+    let dashboard = app.get_admin_dashboard().await;
+    let subscribers = app.get_subscribers().await;
+
+    // Assert
+    assert_eq!(dashboard.status().as_u16(), 200);
+    assert_eq!(subscribers.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    log_in_as(&app, Role::Viewer).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletters() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    log_in_as(&app, Role::Editor).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn only_owners_can_invite_users() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    for role in [Role::Viewer, Role::Editor] {
+        log_in_as(&app, role).await;
+
+        // Act
+        let form = app.get_invitations().await;
+        let invitation = app
+            .post_invitation(&json!({ "email": "colleague@example.com", "role": "viewer" }))
+            .await;
+
+        // Assert
+        assert_eq!(form.status().as_u16(), 403, "{} could see the form", role);
+        assert_eq!(invitation.status().as_u16(), 403, "{} could invite", role);
+    }
+
+    let invitations = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM user_invitations"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(invitations.count, 0);
+}
+
+#[tokio::test]
+async fn the_dashboard_only_links_to_invitations_for_owners() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act - Part 1 - Owner
+    app.test_user.login(&app).await;
+    let owner_html = app.get_admin_dashboard_html().await;
+
+    // Act - Part 2 - Viewer
+    log_in_as(&app, Role::Viewer).await;
+    let viewer_html = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(owner_html.contains(r#"href="/admin/invitations""#));
+    assert!(!viewer_html.contains(r#"href="/admin/invitations""#));
+    assert!(viewer_html.contains("You are signed in as viewer."));
+}
+
+#[tokio::test]
+async fn deleted_users_are_logged_out() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let user = log_in_as(&app, Role::Editor).await;
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
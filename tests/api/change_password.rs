@@ -142,3 +142,93 @@ async fn changing_password_works() {
     let response = app.post_login(&login_body).await;
     TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+/// Try to change the test user's password and return the page showing the outcome.
+async fn change_password_to(app: &TestApp, new_password: &str) -> String {
+    app.test_user.login(app).await;
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    app.get_change_password_html().await
+}
+
+async fn assert_password_unchanged(app: &TestApp) {
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn new_password_must_be_long_enough() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let html_page = change_password_to(&app, "").await;
+
+    // Assert
+    assert!(
+        html_page.contains("<p><i>The new password must be at least 12 characters long.</i></p>")
+    );
+    assert_password_unchanged(&app).await;
+}
+
+#[tokio::test]
+async fn new_password_must_not_be_too_long() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let html_page = change_password_to(&app, &Uuid::new_v4().to_string().repeat(4)).await;
+
+    // Assert
+    assert!(
+        html_page.contains("<p><i>The new password must be at most 128 characters long.</i></p>")
+    );
+    assert_password_unchanged(&app).await;
+}
+
+#[tokio::test]
+async fn new_password_must_not_be_easy_to_guess() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let html_page = change_password_to(&app, "abcdefghijklmnop").await;
+
+    // Assert
+    assert!(html_page.contains("The new password is too easy to guess."));
+    assert_password_unchanged(&app).await;
+}
+
+#[tokio::test]
+async fn new_password_must_not_be_breached() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.password_policy.breached_passwords_file = Some(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/api/fixtures/breached_passwords.txt"
+            )
+            .into(),
+        );
+    })
+    .await;
+
+    // Act
+    let html_page = change_password_to(&app, "this-one-leaked-in-2019").await;
+
+    // Assert
+    assert!(html_page.contains("The new password appears in a list of breached passwords."));
+    assert_password_unchanged(&app).await;
+}
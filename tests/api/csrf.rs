@@ -0,0 +1,124 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn admin_forms_carry_a_csrf_token() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.csrf_token().await.unwrap();
+    let field = format!(r#"<input type="hidden" name="csrf_token" value="{csrf_token}">"#);
+
+    // Act
+    let pages = [
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_publish_newsletter_html().await,
+    ];
+
+    // Assert
+    for page in pages {
+        assert!(page.contains(&field));
+    }
+}
+
+#[tokio::test]
+async fn posts_without_a_csrf_token_are_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - a form submitted from another site cannot know the token
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("Referer", format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<p><i>Your form has expired. Please try again.</i></p>"));
+    // Still logged in
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn posts_with_the_wrong_csrf_token_are_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "csrf_token": "not-the-token",
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_token_is_accepted_as_a_form_field() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.csrf_token().await.unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&[("csrf_token", csrf_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("You have successfully logged out."));
+}
+
+#[tokio::test]
+async fn the_session_cookie_is_not_sent_cross_site() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    let session_cookie = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with("id="))
+        .unwrap();
+    assert!(session_cookie.contains("SameSite=Strict"));
+}
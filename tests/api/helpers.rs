@@ -1,18 +1,22 @@
 //! tests/api/helpers.rs
 
+use chrono::Utc;
 use linkify::{LinkFinder, LinkKind};
 use redact::Secret;
 use reqwest::{Client, Response, Url};
 use serde_aux::prelude::bool_true;
 use serde_json::Value;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero_to_prod::{
-    configuration::{Configuration, DatabaseSettings},
+    authentication::CSRF_HEADER,
+    configuration::{Configuration, DatabaseSettings, Environment, Settings},
+    domain::FormToken,
     email_client::EmailClient,
     issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    mail_domain::MailDomainResolver,
     startup::Application,
     telemetry::Telemetry,
 };
@@ -32,6 +36,16 @@ static TRACING: LazyLock<()> = LazyLock::new(|| {
     }
 });
 
+/// Stands in for DNS: every domain accepts mail except those under `.invalid`.
+pub struct FakeMailDomainResolver;
+
+#[async_trait::async_trait]
+impl MailDomainResolver for FakeMailDomainResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        Ok(!domain.ends_with(".invalid"))
+    }
+}
+
 pub struct ConfirmationLinks {
     pub html: Url,
     pub plain_text: Url,
@@ -45,10 +59,16 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: Client,
     pub email_client: EmailClient,
+    pub hmac_secret: Secret<String>,
 }
 
 impl TestApp {
     pub async fn spawn_app() -> TestApp {
+        Self::spawn_app_with(|_| {}).await
+    }
+
+    /// Spawn the application after applying test-specific configuration overrides.
+    pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
         // The first time `initialize` is invoked the code in `TRACING` is executed.
         // All other invocations will instead skip execution.
         LazyLock::force(&TRACING);
@@ -58,13 +78,14 @@ impl TestApp {
 
         // Randomise configuration to ensure test isolation
         let configuration = {
-            let mut config = Configuration::get().expect("Failed to read configuration.");
+            let mut config = Configuration::for_environment(Environment::Test)
+                .expect("Failed to read configuration.");
 
             // We randomly create new database name for test purposes
             config.database.database_name = Uuid::new_v4().to_string();
-            config.application.port = 0;
             config.email_client.base_url = email_server.uri();
 
+            customise(&mut config);
             config
         };
 
@@ -72,14 +93,18 @@ impl TestApp {
         let connection_pool = TestApp::configure_database(&configuration.database).await;
 
         // Launch the application as a background task
-        let application = Application::build(configuration.clone(), connection_pool.clone())
-            .await
-            .expect("Failed to build application");
+        let application = Application::build_with_resolver(
+            configuration.clone(),
+            connection_pool.clone(),
+            Arc::new(FakeMailDomainResolver),
+        )
+        .await
+        .expect("Failed to build application");
 
         let application_port = application.port();
 
         let address = format!("http://127.0.0.1:{}", application_port);
-        let _ = tokio::spawn(application.run_until_stopped());
+        drop(tokio::spawn(application.run_until_stopped()));
 
         let client = Client::builder()
             .redirect(reqwest::redirect::Policy::none())
@@ -95,6 +120,7 @@ impl TestApp {
             test_user: TestUser::generate(),
             api_client: client,
             email_client: configuration.email_client.client(),
+            hmac_secret: configuration.application.hmac_secret.clone(),
         };
 
         test_app.test_user.store(&test_app.db_pool).await;
@@ -103,6 +129,18 @@ impl TestApp {
     }
 
     async fn configure_database(config: &DatabaseSettings) -> PgPool {
+        let connection_pool = TestApp::create_database(config).await;
+
+        sqlx::migrate!("./migrations")
+            .run(&connection_pool)
+            .await
+            .expect("Failed to migrate the database");
+
+        connection_pool
+    }
+
+    /// Create an empty database, leaving it up to the caller to run migrations.
+    pub async fn create_database(config: &DatabaseSettings) -> PgPool {
         let mut maintenance_settings = DatabaseSettings {
             database_name: "postgres".to_string(),
             username: "postgres".to_string(),
@@ -121,18 +159,10 @@ impl TestApp {
             .await
             .expect("Failed to create database.");
 
-        // Migrate database.
         maintenance_settings.database_name = config.database_name.clone();
-        let connection_pool = PgPool::connect_with(maintenance_settings.connect_options())
+        PgPool::connect_with(maintenance_settings.connect_options())
             .await
-            .expect("Failed to connect to Postgres.");
-
-        sqlx::migrate!("./migrations")
-            .run(&connection_pool)
-            .await
-            .expect("Failed to migrate the database");
-
-        connection_pool
+            .expect("Failed to connect to Postgres.")
     }
 
     pub async fn dispatch_all_pending_emails(&self) {
@@ -147,9 +177,20 @@ impl TestApp {
         }
     }
 
+    /// Submit the subscription form the way a browser would, with a valid form token.
     pub async fn post_subscriptions(&self, body: String) -> Response {
+        self.post_subscriptions_raw(format!("{}&form_token={}", body, self.form_token()))
+            .await
+    }
+
+    /// A subscription form token as rendered by the home page.
+    pub fn form_token(&self) -> String {
+        FormToken::new(Utc::now()).sign(&self.hmac_secret)
+    }
+
+    pub async fn post_subscriptions_raw(&self, body: String) -> Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -157,6 +198,31 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_api_subscriptions<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/api/v1/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The emails received so far, once there are at least `count` of them:
+    /// some emails are sent in the background, after the response.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let received = self.email_server.received_requests().await.unwrap();
+            if received.len() >= count {
+                return received;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("Fewer than {} emails were sent.", count);
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -179,8 +245,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
@@ -190,7 +256,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -202,10 +268,21 @@ impl TestApp {
         assert_eq!(response.headers().get("Location").unwrap(), location);
     }
 
+    pub async fn get_home_html(&self) -> String {
+        self.api_client
+            .get(&self.address)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn get_login_html(&self) -> String {
         let response = self
             .api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.");
@@ -224,7 +301,121 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_invitations(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/invitations", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/invitations")
+            .await
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/forgot", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/forgot", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/password/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/password/two-factor")
+            .await
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/password/two-factor/disable")
+            .await
+            .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -232,18 +423,133 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
-    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    /// The CSRF token of the current session, as embedded in the forms of the admin area.
+    pub async fn csrf_token(&self) -> Option<String> {
+        let html_page = self.get_admin_dashboard_html().await;
+        html_page
+            .split(r#"name="csrf_token" value=""#)
+            .nth(1)?
+            .split('"')
+            .next()
+            .map(ToOwned::to_owned)
+    }
+
+    /// Start a POST to an admin route that carries the CSRF token, as the admin forms do.
+    pub async fn post_admin(&self, path: &str) -> reqwest::RequestBuilder {
+        let csrf_token = self.csrf_token().await.unwrap_or_default();
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .header(CSRF_HEADER, csrf_token)
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Create an API token through the admin area and return it.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let mut form = vec![("name", "ci")];
+        form.extend(scopes.iter().map(|scope| ("scope", *scope)));
+        let html_page = self
+            .post_admin("/admin/api-tokens")
+            .await
+            .form(&form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+
+        html_page
+            .split("<code>")
+            .nth(1)
+            .and_then(|rest| rest.split("</code>").next())
+            .expect("The page did not show a token")
+            .to_owned()
+    }
+
+    pub async fn post_revoke_api_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
+        self.post_admin("/admin/api-tokens/revoke")
+            .await
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Publish an issue through the JSON API, without any session cookie.
+    pub async fn post_api_newsletter(
+        &self,
+        token: Option<&str>,
+        idempotency_key: Option<&str>,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/api/v1/newsletters", &self.address))
+            .json(body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(idempotency_key) = idempotency_key {
+            request = request.header("Idempotency-Key", idempotency_key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/sessions/revoke")
+            .await
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.post_admin("/admin/sessions/revoke-others")
+            .await
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/password")
+            .await
             .form(body)
             .send()
             .await
@@ -254,9 +560,32 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
-    pub async fn post_logout(&self) -> reqwest::Response {
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin("/admin/password/email")
+            .await
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_email_html(&self) -> String {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .get(format!("{}/admin/password/email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.post_admin("/admin/logout")
+            .await
             .send()
             .await
             .expect("Failed to execute request.")
@@ -266,8 +595,8 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
-        self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+        self.post_admin("/admin/newsletters")
+            .await
             .form(body)
             .send()
             .await
@@ -276,7 +605,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -285,4 +614,23 @@ impl TestApp {
     pub async fn get_publish_newsletter_html(&self) -> String {
         self.get_publish_newsletter().await.text().await.unwrap()
     }
+
+    pub async fn get_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
@@ -0,0 +1,453 @@
+use std::time::Duration;
+
+use serde_json::json;
+use sqlx::{Postgres, Transaction};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero_to_prod::idempotency::delete_expired_records;
+
+use crate::helpers::TestApp;
+
+const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn issue() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn issue_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn idempotency_record_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+/// Pretend the request that used `idempotency_key` was processed longer ago than the retention.
+async fn expire(app: &TestApp, idempotency_key: &str) {
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '2 days' WHERE idempotency_key = $1",
+        idempotency_key
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Take the lock a request holds on its idempotency key while it is being processed,
+/// so that requests using the key see it as in progress until the transaction ends.
+async fn hold_in_progress(app: &TestApp, idempotency_key: &str) -> Transaction<'static, Postgres> {
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!(
+            "idempotency:{}:{}",
+            app.test_user.user_id, idempotency_key
+        ))
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+    transaction
+}
+
+#[tokio::test]
+async fn an_expired_idempotency_key_is_processed_as_a_new_request() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    let first: serde_json::Value = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await
+        .json()
+        .await
+        .unwrap();
+    expire(&app, "weekly-issue").await;
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let second: serde_json::Value = response.json().await.unwrap();
+    assert_ne!(first["id"], second["id"]);
+
+    // The key is remembered again from now on.
+    let third: serde_json::Value = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(second["id"], third["id"]);
+}
+
+#[tokio::test]
+async fn the_cleanup_job_deletes_expired_records_in_batches() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    for idempotency_key in ["first-issue", "second-issue", "third-issue"] {
+        app.post_api_newsletter(Some(&token), Some(idempotency_key), &issue())
+            .await;
+    }
+    expire(&app, "first-issue").await;
+    expire(&app, "second-issue").await;
+
+    // Act
+    let n_deleted = delete_expired_records(&app.db_pool, RETENTION, 1)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(n_deleted, 2);
+    assert_eq!(idempotency_record_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    app.post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await;
+    let mut other_issue = issue();
+    other_issue["title"] = "Another title".into();
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &other_issue)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(issue_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn the_same_request_with_its_fields_reordered_is_a_retry() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    let reordered: serde_json::Value = serde_json::from_str(
+        r#"{
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text"
+        }"#,
+    )
+    .unwrap();
+    app.post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await;
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &reordered)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(issue_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn resubmitting_the_newsletter_form_with_different_content_is_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut body = issue();
+    body["idempotency_key"] = idempotency_key.into();
+    app.post_publish_newsletter(&body).await;
+    body["title"] = "Edited title".into();
+
+    // Act
+    let response = app.post_publish_newsletter(&body).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>This form has already been submitted with different values. Please try again.</i></p>"
+    ));
+    assert_eq!(issue_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn an_api_request_whose_key_is_in_progress_is_told_to_retry_later() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config
+            .idempotency
+            .concurrent_requests
+            .newsletter_api
+            .max_wait_milliseconds = 0;
+        config
+            .idempotency
+            .concurrent_requests
+            .newsletter_api
+            .retry_after_seconds = 3;
+    })
+    .await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    let in_progress = hold_in_progress(&app, "weekly-issue").await;
+
+    // Act
+    let response = app
+        .post_api_newsletter(Some(&token), Some("weekly-issue"), &issue())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "3");
+    in_progress.rollback().await.unwrap();
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_form_submission_waits_for_the_request_in_progress_with_its_key() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config
+            .idempotency
+            .concurrent_requests
+            .newsletter_form
+            .max_wait_milliseconds = 5000;
+    })
+    .await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut body = issue();
+    body["idempotency_key"] = idempotency_key.clone().into();
+    let in_progress = hold_in_progress(&app, &idempotency_key).await;
+
+    // Act
+    let release = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        in_progress.rollback().await.unwrap();
+    };
+    let (response, _) = tokio::join!(app.post_publish_newsletter(&body), release);
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    assert_eq!(issue_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_form_submission_gives_up_waiting_after_the_configured_time() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config
+            .idempotency
+            .concurrent_requests
+            .newsletter_form
+            .max_wait_milliseconds = 200;
+    })
+    .await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut body = issue();
+    body["idempotency_key"] = idempotency_key.clone().into();
+    let in_progress = hold_in_progress(&app, &idempotency_key).await;
+
+    // Act
+    let response = app.post_publish_newsletter(&body).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>Your previous submission is still being processed. Please check again in a moment.</i></p>"
+    ));
+    in_progress.rollback().await.unwrap();
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_newsletter_form_requires_an_idempotency_key() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_publish_newsletter(&issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn resubmitting_the_subscription_form_sends_a_single_confirmation_email() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}&idempotency_key={}",
+        app.form_token(),
+        uuid::Uuid::new_v4()
+    );
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first = app.post_subscriptions_raw(body.clone()).await;
+    let second = app.post_subscriptions_raw(body).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn resubmitting_the_password_form_does_not_check_the_old_password_again() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let body = json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_change_password(&body).await;
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    app.get_change_password_html().await;
+
+    // Act
+    let response = app.post_change_password(&body).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(!html_page.contains("The current password is incorrect."));
+}
+
+#[tokio::test]
+async fn passwords_are_left_out_of_the_stored_fingerprint() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = |new_password: &str| {
+        json!({
+            "current_password": "not-the-password",
+            "new_password": new_password,
+            "new_password_check": new_password,
+            "idempotency_key": &idempotency_key,
+        })
+    };
+    app.post_change_password(&body("first-attempt")).await;
+
+    // Act
+    let response = app.post_change_password(&body("second-attempt")).await;
+
+    // Assert - a retry, not a different request
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    assert!(!app
+        .get_change_password_html()
+        .await
+        .contains("This form has already been submitted with different values."));
+}
+
+#[tokio::test]
+async fn a_failed_password_change_can_be_retried_with_the_same_key() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let body = |current_password: &str| {
+        json!({
+            "current_password": current_password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+            "idempotency_key": &idempotency_key,
+        })
+    };
+    app.post_change_password(&body("not-the-password")).await;
+    assert!(app
+        .get_change_password_html()
+        .await
+        .contains("The current password is incorrect."));
+
+    // Act
+    let response = app
+        .post_change_password(&body(&app.test_user.password))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    assert!(app
+        .get_change_password_html()
+        .await
+        .contains("Your password has been changed."));
+}
+
+#[tokio::test]
+async fn anonymous_clients_do_not_share_idempotency_keys() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.application.trusted_proxies = vec!["127.0.0.1".into()];
+    })
+    .await;
+    let idempotency_key = uuid::Uuid::new_v4();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut responses = Vec::new();
+    for (client, email) in [
+        ("198.51.100.1", "ursula_le_guin%40gmail.com"),
+        ("198.51.100.2", "octavia_butler%40gmail.com"),
+    ] {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", client)
+            .body(format!(
+                "name=reader&email={}&form_token={}&idempotency_key={}",
+                email,
+                app.form_token(),
+                idempotency_key
+            ))
+            .send()
+            .await
+            .unwrap();
+        responses.push(response.status().as_u16());
+    }
+
+    // Assert - neither is mistaken for a retry of the other
+    assert_eq!(responses, vec![200, 200]);
+    // Mock verifies on Drop that both confirmation emails were sent
+}
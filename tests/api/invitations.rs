@@ -0,0 +1,262 @@
+//! tests/api/invitations.rs
+
+use reqwest::Url;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+/// Invite `email` as the test user and return the invitation link that was mailed.
+async fn invite(app: &TestApp, email: &str) -> Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.test_user.login(app).await;
+    let response = app
+        .post_invitation(&json!({ "email": email, "role": "editor" }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/invitations");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
+
+fn invitation_token(link: &Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "invitation_token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_invite_users() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let form = app.get_invitations().await;
+    let invitation = app
+        .post_invitation(&json!({ "email": "colleague@example.com", "role": "editor" }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&form, "/login");
+    TestApp::assert_is_redirect_to(&invitation, "/login");
+}
+
+#[tokio::test]
+async fn an_invited_colleague_can_create_an_account_and_log_in() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = invite(&app, "colleague@example.com").await;
+
+    let html_page = app.get_invitations().await.text().await.unwrap();
+    assert!(html_page.contains("An invitation has been sent to colleague@example.com."));
+
+    app.post_logout().await;
+
+    // Act - Part 1 - Open the invitation link
+    let html_page = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(200, html_page.status().as_u16());
+    assert!(html_page
+        .text()
+        .await
+        .unwrap()
+        .contains("colleague@example.com"));
+
+    // Act - Part 2 - Choose credentials
+    let response = app
+        .post_accept_invitation(&json!({
+            "invitation_token": invitation_token(&link),
+            "username": "colleague",
+            "password": "a-long-and-secret-password",
+            "password_check": "a-long-and-secret-password",
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Log in with them
+    let response = app
+        .post_login(&json!({
+            "username": "colleague",
+            "password": "a-long-and-secret-password",
+        }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT email, role FROM users WHERE username = 'colleague'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.email.as_deref(), Some("colleague@example.com"));
+    assert_eq!(user.role, "editor");
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_used_once() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = invite(&app, "colleague@example.com").await;
+    app.post_logout().await;
+
+    let body = |username: &str| {
+        json!({
+            "invitation_token": invitation_token(&link),
+            "username": username,
+            "password": "a-long-and-secret-password",
+            "password_check": "a-long-and-secret-password",
+        })
+    };
+
+    // Act
+    let first = app.post_accept_invitation(&body("colleague")).await;
+    let second = app.post_accept_invitation(&body("impostor")).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&first, "/login");
+    assert_eq!(401, second.status().as_u16());
+    assert_eq!(
+        401,
+        app.api_client
+            .get(link)
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16()
+    );
+}
+
+#[tokio::test]
+async fn expired_invitations_are_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = invite(&app, "colleague@example.com").await;
+    app.post_logout().await;
+
+    sqlx::query!("UPDATE user_invitations SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_invitee_must_pick_a_free_username_and_matching_passwords() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = invite(&app, "colleague@example.com").await;
+    app.post_logout().await;
+
+    let test_cases = vec![
+        (
+            app.test_user.username.clone(),
+            "a-long-and-secret-password",
+            "That username is already taken.",
+        ),
+        (
+            "colleague".to_string(),
+            "another-password",
+            "You entered two different passwords",
+        ),
+        (
+            "  ".to_string(),
+            "a-long-and-secret-password",
+            "Please choose a username.",
+        ),
+    ];
+
+    for (username, password_check, message) in test_cases {
+        // Act
+        let response = app
+            .post_accept_invitation(&json!({
+                "invitation_token": invitation_token(&link),
+                "username": username,
+                "password": "a-long-and-secret-password",
+                "password_check": password_check,
+            }))
+            .await;
+
+        // Assert
+        let location = response.headers()["Location"].to_str().unwrap();
+        assert!(location.starts_with("/invitations/accept?invitation_token="));
+        let html_page = app
+            .api_client
+            .get(link.clone())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(html_page.contains(message), "Missing: {}", message);
+    }
+
+    let users = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users.count, 2);
+}
+
+#[tokio::test]
+async fn a_second_invitation_for_the_same_email_cannot_create_another_account() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    for _ in 0..2 {
+        app.post_invitation(&json!({ "email": "colleague@example.com", "role": "editor" }))
+            .await;
+    }
+    app.post_logout().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let links: Vec<_> = email_requests
+        .iter()
+        .map(|request| app.get_confirmation_links(request).html)
+        .collect();
+    let body = |link: &Url, username: &str| {
+        json!({
+            "invitation_token": invitation_token(link),
+            "username": username,
+            "password": "a-long-and-secret-password",
+            "password_check": "a-long-and-secret-password",
+        })
+    };
+
+    // Act
+    let first = app
+        .post_accept_invitation(&body(&links[0], "colleague"))
+        .await;
+    let second = app
+        .post_accept_invitation(&body(&links[1], "colleague2"))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&first, "/login");
+    assert_eq!(409, second.status().as_u16());
+    assert!(second
+        .text()
+        .await
+        .unwrap()
+        .contains("colleague@example.com already has an account."));
+}
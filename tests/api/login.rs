@@ -44,3 +44,232 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+async fn spawn_app_with_lockout_after(failures: u32) -> TestApp {
+    TestApp::spawn_app_with(|config| {
+        config.login_lockout.max_consecutive_failures = failures;
+        config.login_lockout.lockout_seconds = 900;
+    })
+    .await
+}
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": "wrong-password"
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn login_attempts_are_recorded() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    fail_login(&app, &app.test_user.username).await;
+    app.test_user.login(&app).await;
+
+    // Assert
+    let attempts = sqlx::query!(
+        "SELECT user_id, ip, outcome FROM login_attempts WHERE username = $1 ORDER BY id",
+        app.test_user.username
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0].outcome, "failure");
+    assert_eq!(attempts[1].outcome, "success");
+    assert_eq!(attempts[1].user_id, Some(app.test_user.user_id));
+    assert_eq!(attempts[1].ip.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn an_account_is_locked_after_too_many_consecutive_failures() {
+    // Arrange
+    let app = spawn_app_with_lockout_after(3).await;
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act - Part 1 - Use the right password
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains("<p><i>Too many failed login attempts. Please try again later.</i></p>")
+    );
+    let response = app.get_admin_dashboard().await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    // Arrange
+    let app = spawn_app_with_lockout_after(3).await;
+    fail_login(&app, &app.test_user.username).await;
+    fail_login(&app, &app.test_user.username).await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    fail_login(&app, &app.test_user.username).await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn unknown_usernames_are_locked_out_like_real_ones() {
+    // Arrange
+    let app = spawn_app_with_lockout_after(2).await;
+    for username in [app.test_user.username.as_str(), "nobody"] {
+        fail_login(&app, username).await;
+        fail_login(&app, username).await;
+    }
+
+    // Act
+    fail_login(&app, &app.test_user.username).await;
+    let known_html = app.get_login_html().await;
+    fail_login(&app, "nobody").await;
+    let unknown_html = app.get_login_html().await;
+
+    // Assert
+    assert_eq!(known_html, unknown_html);
+    assert!(known_html.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn the_lock_expires() {
+    // Arrange
+    let app = spawn_app_with_lockout_after(2).await;
+    fail_login(&app, &app.test_user.username).await;
+    fail_login(&app, &app.test_user.username).await;
+    sqlx::query!("UPDATE login_attempts SET attempted_at = attempted_at - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_dashboard_lists_recent_failed_logins() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    fail_login(&app, "intruder").await;
+
+    // Act
+    app.test_user.login(&app).await;
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("<td>intruder</td><td>127.0.0.1</td><td>failure</td>"));
+}
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_on_login() {
+    // Arrange - the test user is stored with m=15000,t=2,p=1
+    let app = TestApp::spawn_app_with(|config| {
+        config.password_hashing.memory_kib = 19456;
+        config.password_hashing.iterations = 3;
+    })
+    .await;
+    let outdated_hash = stored_password_hash(&app).await;
+
+    // Act
+    app.test_user.login(&app).await;
+
+    // Assert - the hash is replaced in the background
+    let mut upgraded_hash = outdated_hash.clone();
+    for _ in 0..50 {
+        upgraded_hash = stored_password_hash(&app).await;
+        if upgraded_hash != outdated_hash {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(upgraded_hash.contains("m=19456,t=3,p=1"));
+
+    // The same password still works against the new hash
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_current_password_hash_is_left_alone() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.password_hashing.memory_kib = 15000;
+        config.password_hashing.iterations = 2;
+        config.password_hashing.parallelism = 1;
+    })
+    .await;
+    let stored_hash = stored_password_hash(&app).await;
+
+    // Act
+    app.test_user.login(&app).await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    assert_eq!(stored_password_hash(&app).await, stored_hash);
+}
+
+#[tokio::test]
+async fn a_failed_login_does_not_rehash_the_password() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.password_hashing.memory_kib = 19456;
+    })
+    .await;
+    let stored_hash = stored_password_hash(&app).await;
+
+    // Act
+    fail_login(&app, &app.test_user.username).await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    assert_eq!(stored_password_hash(&app).await, stored_hash);
+}
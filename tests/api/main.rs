@@ -1,11 +1,24 @@
 //! tests/api/main.rs
 
 mod admin_dashboard;
+mod admin_subscribers;
+mod api_subscriptions;
+mod api_tokens;
+mod authorization;
 mod change_password;
+mod csrf;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod invitations;
 mod login;
 mod newsletter;
+mod password_reset;
+mod problem_details;
+mod rate_limit;
+mod sessions;
+mod subscriber_email_merge;
 mod subscriptions;
 mod subscriptions_confirm;
 mod test_user;
+mod two_factor;
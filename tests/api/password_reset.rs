@@ -0,0 +1,314 @@
+//! tests/api/password_reset.rs
+
+use reqwest::Url;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+/// Ask for a reset link for the test user and return the link that was mailed.
+async fn request_reset_link(app: &TestApp) -> Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password(&json!({ "email": app.test_user.email }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/login/forgot");
+
+    let email_request = &app.wait_for_emails(1).await[0];
+    app.get_confirmation_links(email_request).html
+}
+
+fn reset_token(link: &Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "reset_token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+fn reset_body(link: &Url, new_password: &str) -> serde_json::Value {
+    json!({
+        "reset_token": reset_token(link),
+        "new_password": new_password,
+        "new_password_check": new_password,
+    })
+}
+
+#[tokio::test]
+async fn the_response_does_not_reveal_whether_an_account_exists() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Known email
+    let known = app
+        .post_forgot_password(&json!({ "email": app.test_user.email }))
+        .await;
+    let known_html = app.get_forgot_password_html().await;
+
+    // Act - Part 2 - Unknown email
+    let unknown = app
+        .post_forgot_password(&json!({ "email": "nobody@example.com" }))
+        .await;
+    let unknown_html = app.get_forgot_password_html().await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&known, "/login/forgot");
+    TestApp::assert_is_redirect_to(&unknown, "/login/forgot");
+    assert_eq!(known_html, unknown_html);
+    assert!(known_html.contains("we have sent it a link to reset the password"));
+    app.wait_for_emails(1).await;
+    // Mock verifies on Drop that only one email was sent
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_the_user_choose_a_new_password() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = request_reset_link(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Follow the link
+    let form = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(form.status().as_u16(), 200);
+
+    // Act - Part 2 - Reset the password
+    let response = app
+        .post_reset_password(&reset_body(&link, &new_password))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Log in with the old password
+    let response = app
+        .post_login(&json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 4 - Log in with the new password
+    let response = app
+        .post_login(&json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = request_reset_link(&app).await;
+
+    // Act
+    let first = app
+        .post_reset_password(&reset_body(&link, "first-new-password"))
+        .await;
+    let second = app
+        .post_reset_password(&reset_body(&link, "second-new-password"))
+        .await;
+    let form = app.api_client.get(link.clone()).send().await.unwrap();
+
+    // Assert
+    TestApp::assert_is_redirect_to(&first, "/login");
+    assert_eq!(second.status().as_u16(), 401);
+    assert_eq!(form.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = request_reset_link(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_reset_password(&reset_body(&link, "a-new-password"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let link = request_reset_link(&app).await;
+
+    // Act
+    let response = app
+        .post_reset_password(&json!({
+            "reset_token": reset_token(&link),
+            "new_password": "a-new-password",
+            "new_password_check": "another-new-password",
+        }))
+        .await;
+
+    // Assert
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/login/reset?reset_token="));
+    let html_page = app
+        .api_client
+        .get(link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_the_user_out_everywhere() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    let link = request_reset_link(&app).await;
+
+    // Act
+    app.post_reset_password(&reset_body(&link, "a-new-password"))
+        .await;
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_response_does_not_wait_for_the_reset_email() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let start = Instant::now();
+    let response = app
+        .post_forgot_password(&json!({ "email": app.test_user.email }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login/forgot");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // Mock servers are pooled: the email must not reach the one of another test.
+    app.wait_for_emails(1).await;
+}
+
+#[tokio::test]
+async fn users_can_change_the_email_reset_links_are_sent_to() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Change the email
+    let response = app
+        .post_change_email(&json!({
+            "email": "new-address@example.com",
+            "current_password": &app.test_user.password,
+        }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/password/email");
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page.contains("Your email address has been changed."));
+    assert!(html_page.contains("Password reset links are sent to new-address@example.com."));
+
+    // Act - Part 2 - Ask for a reset link
+    app.post_logout().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_forgot_password(&json!({ "email": "new-address@example.com" }))
+        .await;
+
+    // Assert
+    let email_request = &app.wait_for_emails(1).await[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "new-address@example.com");
+}
+
+#[tokio::test]
+async fn changing_the_email_requires_the_current_password_and_a_free_address() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, 'colleague', 'not-a-hash', 'colleague@example.com', 'editor')",
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let test_cases = [
+        (
+            "new-address@example.com",
+            "wrong-password",
+            "The current password is incorrect.",
+        ),
+        (
+            "Colleague@Example.com",
+            app.test_user.password.as_str(),
+            "Another account already uses that email address.",
+        ),
+    ];
+
+    for (email, current_password, message) in test_cases {
+        // Act
+        let response = app
+            .post_change_email(&json!({
+                "email": email,
+                "current_password": current_password,
+            }))
+            .await;
+
+        // Assert
+        TestApp::assert_is_redirect_to(&response, "/admin/password/email");
+        let html_page = app.get_change_email_html().await;
+        assert!(html_page.contains(message), "Missing: {}", message);
+    }
+
+    let user = sqlx::query!(
+        "SELECT email FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.email, Some(app.test_user.email.clone()));
+}
@@ -0,0 +1,162 @@
+//! tests/api/problem_details.rs
+
+use serde_json::Value;
+
+use crate::helpers::TestApp;
+
+async fn assert_is_problem(response: reqwest::Response, status: u16) -> Value {
+    assert_eq!(status, response.status().as_u16());
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/problem+json"
+    );
+    let request_id = response
+        .headers()
+        .get("X-Request-Id")
+        .expect("Missing request id header")
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let problem: Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], status);
+    assert_eq!(problem["request_id"], request_id);
+    assert!(problem["title"].is_string());
+
+    problem
+}
+
+#[tokio::test]
+async fn json_clients_get_problem_details_for_validation_errors() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(format!(
+            "name=Ursula&email=definitely-not-an-email&form_token={}",
+            app.form_token()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let problem = assert_is_problem(response, 400).await;
+    assert_eq!(problem["instance"], "/subscriptions");
+    assert_eq!(
+        problem["detail"],
+        "definitely-not-an-email is not a valid subscriber email."
+    );
+}
+
+#[tokio::test]
+async fn html_clients_keep_the_plain_error_response() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=definitely-not-an-email".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert!(response.headers().get("X-Request-Id").is_some());
+    assert_ne!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn unexpected_errors_do_not_leak_their_cause_chain() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+            app.form_token()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let problem = assert_is_problem(response, 500).await;
+    assert!(problem.get("detail").is_none());
+    assert!(!problem.to_string().contains("Caused by"));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_are_reported_as_problems() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            &app.address
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_problem(response, 401).await;
+}
+
+#[tokio::test]
+async fn json_clients_are_not_redirected_to_the_login_page() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act - Part 1 - Anonymous access to the admin area
+    let response = app
+        .api_client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.headers().get("Location").is_none());
+    assert_is_problem(response, 401).await;
+
+    // Act - Part 2 - Failed login
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("Accept", "application/json")
+        .form(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let problem = assert_is_problem(response, 401).await;
+    assert_eq!(problem["detail"], "Authentication failed");
+}
@@ -0,0 +1,188 @@
+//! tests/api/rate_limit.rs
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero_to_prod::configuration::Quota;
+
+use crate::helpers::TestApp;
+
+fn quota(max_requests: u64) -> Option<Quota> {
+    Some(Quota {
+        max_requests,
+        window_seconds: 3600,
+    })
+}
+
+#[tokio::test]
+async fn subscribing_too_often_with_the_same_email_returns_a_429() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.subscriptions.per_identity = quota(1);
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn the_email_limit_ignores_case_and_does_not_affect_other_emails() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.subscriptions.per_identity = quota(1);
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let same_email = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+    let other_email = app
+        .post_subscriptions("name=le%20guin&email=other%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(429, same_email.status().as_u16());
+    assert_eq!(200, other_email.status().as_u16());
+}
+
+#[tokio::test]
+async fn logging_in_too_often_from_the_same_ip_returns_a_429() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.login.per_ip = quota(3);
+    })
+    .await;
+
+    // Act
+    for _ in 0..3 {
+        let response = app
+            .post_login(&json!({
+                "username": Uuid::new_v4().to_string(),
+                "password": Uuid::new_v4().to_string(),
+            }))
+            .await;
+        assert_eq!(303, response.status().as_u16());
+    }
+    let response = app
+        .post_login(&json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn rate_limited_api_requests_get_a_problem_document() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.subscriptions.per_ip = quota(0);
+    })
+    .await;
+
+    // Act
+    let response = app
+        .post_api_subscriptions(&json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    assert_eq!(
+        "application/problem+json",
+        response.headers()["Content-Type"]
+    );
+    assert!(response.headers().contains_key("Retry-After"));
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(429, body["status"]);
+    assert_eq!("/api/v1/subscriptions", body["instance"]);
+}
+
+async fn post_login_forwarded_for(app: &TestApp, forwarded_for: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", forwarded_for)
+        .form(&json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn clients_cannot_escape_the_ip_limit_by_forging_forwarded_addresses() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.login.per_ip = quota(1);
+    })
+    .await;
+
+    // Act
+    let first = post_login_forwarded_for(&app, "198.51.100.1").await;
+    let second = post_login_forwarded_for(&app, "198.51.100.2").await;
+
+    // Assert
+    assert_eq!(303, first.status().as_u16());
+    assert_eq!(429, second.status().as_u16());
+}
+
+#[tokio::test]
+async fn addresses_forwarded_by_a_trusted_proxy_are_limited_separately() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.login.per_ip = quota(1);
+        config.application.trusted_proxies = vec!["127.0.0.1".into()];
+    })
+    .await;
+
+    // Act
+    let first = post_login_forwarded_for(&app, "198.51.100.1").await;
+    let other_client = post_login_forwarded_for(&app, "198.51.100.2").await;
+    let same_client = post_login_forwarded_for(&app, "192.0.2.99, 198.51.100.1").await;
+
+    // Assert
+    assert_eq!(303, first.status().as_u16());
+    assert_eq!(303, other_client.status().as_u16());
+    assert_eq!(429, same_client.status().as_u16());
+}
@@ -0,0 +1,183 @@
+use reqwest::Client;
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+use crate::test_user::TestUser;
+
+/// Log `user` in with a separate cookie jar, as if from another browser.
+async fn log_in_from_another_device(app: &TestApp, user: &TestUser, user_agent: &str) -> Client {
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(user_agent)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn session_ids(app: &TestApp, user_agent: &str) -> Vec<Uuid> {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_agent = $1",
+        user_agent
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.session_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_login() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    log_in_from_another_device(&app, &app.test_user, "Phone Browser/1.0").await;
+
+    // Act
+    let html_page = app.get_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("Phone Browser/1.0"));
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert_eq!(html_page.matches("Revoke</button>").count(), 1);
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let phone = log_in_from_another_device(&app, &app.test_user, "Phone Browser/1.0").await;
+    let phone_session_id = session_ids(&app, "Phone Browser/1.0").await[0];
+
+    // Act
+    let response = app
+        .post_revoke_session(&serde_json::json!({ "session_id": phone_session_id }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("The session has been revoked."));
+    TestApp::assert_is_redirect_to(&get_dashboard(&app, &phone).await, "/login");
+    // The current session is unaffected
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_revoked_at_once() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let phone = log_in_from_another_device(&app, &app.test_user, "Phone Browser/1.0").await;
+    let tablet = log_in_from_another_device(&app, &app.test_user, "Tablet Browser/1.0").await;
+
+    // Act
+    let response = app.post_revoke_other_sessions().await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("2 other session(s) have been logged out."));
+    assert!(!html_page.contains("Phone Browser/1.0"));
+    TestApp::assert_is_redirect_to(&get_dashboard(&app, &phone).await, "/login");
+    TestApp::assert_is_redirect_to(&get_dashboard(&app, &tablet).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn changing_password_logs_out_other_sessions() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let phone = log_in_from_another_device(&app, &app.test_user, "Phone Browser/1.0").await;
+
+    // Act
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/password");
+    TestApp::assert_is_redirect_to(&get_dashboard(&app, &phone).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let colleague = TestUser::generate();
+    colleague.store(&app.db_pool).await;
+    let colleague_client =
+        log_in_from_another_device(&app, &colleague, "Colleague Browser/1.0").await;
+    let colleague_session_id = session_ids(&app, "Colleague Browser/1.0").await[0];
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_revoke_session(&serde_json::json!({ "session_id": colleague_session_id }))
+        .await;
+
+    // Assert
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("That session does not exist or has already ended."));
+    assert_eq!(
+        get_dashboard(&app, &colleague_client)
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    log_in_from_another_device(&app, &app.test_user, "Phone Browser/1.0").await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    let active = sqlx::query!(
+        "SELECT count(*) AS \"count!\" FROM user_sessions WHERE user_id = $1 AND revoked_at IS NULL",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(active.count, 1);
+}
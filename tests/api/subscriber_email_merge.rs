@@ -0,0 +1,184 @@
+//! tests/api/subscriber_email_merge.rs
+
+use sqlx::PgPool;
+use uuid::Uuid;
+use zero_to_prod::configuration::{Configuration, Environment};
+
+use crate::helpers::TestApp;
+
+const MERGE_MIGRATION: i64 = 20261019090000;
+
+/// A database migrated up to, but excluding, `version`.
+async fn database_before_migration(version: i64) -> PgPool {
+    let mut config =
+        Configuration::for_environment(Environment::Test).expect("Failed to read configuration.");
+    config.database.database_name = Uuid::new_v4().to_string();
+    let pool = TestApp::create_database(&config.database).await;
+
+    for migration in sqlx::migrate!("./migrations")
+        .iter()
+        .filter(|migration| migration.version < version)
+    {
+        sqlx::raw_sql(&migration.sql)
+            .execute(&pool)
+            .await
+            .expect("Failed to run migration");
+    }
+
+    pool
+}
+
+async fn run_migration(pool: &PgPool, version: i64) {
+    let migrator = sqlx::migrate!("./migrations");
+    let migration = migrator
+        .iter()
+        .find(|migration| migration.version == version)
+        .expect("Unknown migration");
+
+    sqlx::raw_sql(&migration.sql)
+        .execute(pool)
+        .await
+        .expect("Failed to run migration");
+}
+
+async fn insert_subscriber(pool: &PgPool, email: &str, status: &str, days_ago: i32) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Alice', now() - make_interval(days => $3), $4)
+        "#,
+    )
+    .bind(id)
+    .bind(email)
+    .bind(days_ago)
+    .bind(status)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)",
+    )
+    .bind(id.to_string())
+    .bind(id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    // The user agent tells the consents apart.
+    sqlx::query(
+        r#"
+        INSERT INTO subscription_consents (subscriber_id, source, consent_text_version, user_agent, consented_at)
+        VALUES ($1, 'form', '2024-11-01', $2, now())
+        "#,
+    )
+    .bind(id)
+    .bind(id.to_string())
+    .execute(pool)
+    .await
+    .unwrap();
+
+    id
+}
+
+#[tokio::test]
+async fn subscribers_whose_emails_only_differ_by_case_are_merged() {
+    // Arrange
+    let pool = database_before_migration(MERGE_MIGRATION).await;
+
+    let oldest_pending =
+        insert_subscriber(&pool, "Alice@Example.com", "pending_confirmation", 3).await;
+    let confirmed = insert_subscriber(&pool, "alice@example.com", "confirmed", 2).await;
+    let newest_pending =
+        insert_subscriber(&pool, "ALICE@example.com", "pending_confirmation", 1).await;
+    let unrelated = insert_subscriber(&pool, "Bob@Example.com", "pending_confirmation", 1).await;
+
+    // Act
+    run_migration(&pool, MERGE_MIGRATION).await;
+
+    // Assert
+    let subscribers: Vec<(Uuid, String, String)> =
+        sqlx::query_as("SELECT id, email, status FROM subscriptions ORDER BY lower(email)")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        subscribers,
+        vec![
+            (confirmed, "alice@example.com".into(), "confirmed".into()),
+            (
+                unrelated,
+                "Bob@Example.com".into(),
+                "pending_confirmation".into()
+            ),
+        ]
+    );
+
+    // Confirmation links mailed to the merged duplicates now point to the survivor,
+    // and so do the consents given through them.
+    for duplicate in [oldest_pending, confirmed, newest_pending] {
+        let subscriber_id: Uuid = sqlx::query_scalar(
+            "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
+        )
+        .bind(duplicate.to_string())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(subscriber_id, confirmed);
+
+        let subscriber_id: Uuid = sqlx::query_scalar(
+            "SELECT subscriber_id FROM subscription_consents WHERE user_agent = $1",
+        )
+        .bind(duplicate.to_string())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(subscriber_id, confirmed);
+    }
+}
+
+#[tokio::test]
+async fn the_most_advanced_status_wins_even_when_it_is_the_newest() {
+    // Arrange
+    let pool = database_before_migration(MERGE_MIGRATION).await;
+
+    insert_subscriber(&pool, "carol@example.com", "pending_confirmation", 5).await;
+    let confirmed = insert_subscriber(&pool, "Carol@example.com", "confirmed", 1).await;
+
+    // Act
+    run_migration(&pool, MERGE_MIGRATION).await;
+
+    // Assert
+    let (id, email): (Uuid, String) = sqlx::query_as("SELECT id, email FROM subscriptions")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(id, confirmed);
+    assert_eq!(email, "Carol@example.com");
+}
+
+#[tokio::test]
+async fn emails_that_only_differ_by_case_cannot_both_be_stored() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let insert = |email: &'static str| {
+        sqlx::query(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, 'Dave', now(), 'confirmed')
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(email)
+        .execute(&app.db_pool)
+    };
+
+    // Act
+    let first = insert("Dave@Example.com").await;
+    let second = insert("dave@example.com").await;
+
+    // Assert
+    assert!(first.is_ok());
+    assert!(second.is_err());
+}
@@ -113,7 +113,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
@@ -174,6 +174,14 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
         ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
         ("name=Ursula&email=", "empty email"),
         ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        (
+            "name=Ursula&email=ursula%40mailinator.com",
+            "disposable email",
+        ),
+        (
+            "name=Ursula&email=ursula%40gmial.com",
+            "mistyped email domain",
+        ),
     ];
 
     for (body, description) in test_cases {
@@ -189,3 +197,285 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribe_records_the_consent_of_the_new_subscriber() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "consent-test-agent")
+        .body(format!("{}&form_token={}", body, app.form_token()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let saved = sqlx::query!(
+        r#"
+        SELECT source, consent_text_version, source_ip, user_agent, confirmed_at
+        FROM subscription_consents
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent.");
+
+    assert_eq!(saved.source, "form");
+    assert_eq!(
+        saved.consent_text_version,
+        zero_to_prod::domain::CONSENT_TEXT_VERSION
+    );
+    assert_eq!(saved.source_ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(saved.user_agent.as_deref(), Some("consent-test-agent"));
+    assert!(saved.confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn submissions_that_fill_the_honeypot_are_accepted_but_discarded() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(0, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn submissions_without_a_valid_form_token_are_accepted_but_discarded() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token=1700000000.deadbeef",
+    ];
+
+    for body in test_cases {
+        // Act
+        let response = app.post_subscriptions_raw(body.into()).await;
+
+        // Assert
+        assert_eq!(200, response.status().as_u16());
+    }
+    assert_eq!(0, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn submissions_posted_too_quickly_after_rendering_the_form_are_discarded() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.subscription_form.min_fill_seconds = 60;
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(0, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn the_home_page_form_token_is_accepted() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let html = app.get_home_html().await;
+    let form_token = html
+        .split(r#"name="form_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .expect("The home page has no form token");
+
+    // Act
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=&form_token={}",
+        form_token
+    );
+    let response = app.post_subscriptions_raw(body).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn subscribe_suggests_a_correction_for_mistyped_domains() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmial.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Did you mean ursula@gmail.com?"));
+}
+
+#[tokio::test]
+async fn subscribe_shows_the_form_again_to_keep_or_correct_a_mistyped_domain() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmial.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"name="email" value="ursula@gmial.com""#));
+    assert!(html.contains(r#"name="keep_email""#));
+}
+
+#[tokio::test]
+async fn subscribe_accepts_a_mistyped_looking_domain_the_subscriber_chose_to_keep() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmial.com&keep_email=true".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn subscribe_stores_the_email_trimmed_with_a_lowercase_domain() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = "name=le%20guin&email=%20Ursula_Le_Guin%40GMail.com%20";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "Ursula_Le_Guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribing_twice_with_the_same_email_returns_a_409() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&app).await);
+}
+
+#[tokio::test]
+async fn subscribing_again_with_a_differently_cased_email_returns_a_409() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&app).await);
+}
+
+async fn count_subscriptions(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
@@ -1,21 +1,30 @@
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
 use sqlx::PgPool;
 use uuid::Uuid;
+use zero_to_prod::authentication::Role;
 
 use crate::helpers::TestApp;
 
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
+    pub email: String,
     pub password: String,
+    pub role: Role,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role(Role::Owner)
+    }
+
+    pub fn generate_with_role(role: Role) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
+            email: format!("{}@example.com", Uuid::new_v4()),
             password: "everythinghastostartsomewhere".into(),
+            role,
         }
     }
 
@@ -43,12 +52,14 @@ impl TestUser {
 
         sqlx::query!(
             r#"
-            INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (user_id, username, email, password_hash, role)
+            VALUES ($1, $2, $3, $4, $5)
             "#,
             self.user_id,
             self.username,
-            password_hash
+            self.email,
+            password_hash,
+            self.role.as_str()
         )
         .execute(pool)
         .await
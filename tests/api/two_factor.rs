@@ -0,0 +1,322 @@
+//! tests/api/two_factor.rs
+
+use reqwest::Client;
+use serde_json::json;
+use zero_to_prod::authentication::{enable_two_factor, TotpSecret};
+use zero_to_prod::configuration::Quota;
+
+use crate::helpers::TestApp;
+
+fn current_code(secret: &TotpSecret) -> String {
+    secret.generate_code(chrono::Utc::now().timestamp() as u64)
+}
+
+/// Turn two-factor authentication on for the test user without going through the UI.
+async fn enroll_test_user(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    let secret = TotpSecret::generate();
+    let recovery_codes = vec!["aaaaa-bbbbb".to_string(), "ccccc-ddddd".to_string()];
+    enable_two_factor(
+        app.test_user.user_id,
+        &secret,
+        &recovery_codes,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    (secret, recovery_codes)
+}
+
+async fn log_in_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn users_can_enroll_with_a_code_from_their_app() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Get a secret
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("otpauth://totp/Newsletter:"));
+    let secret = html_page
+        .split("Secret: <code>")
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .unwrap();
+    let secret = TotpSecret::parse(secret.to_owned()).unwrap();
+
+    // Act - Part 2 - Confirm it
+    let response = app
+        .post_two_factor(&json!({ "code": current_code(&secret) }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication is now enabled."));
+    assert_eq!(html_page.matches("<li><code>").count(), 10);
+
+    let user = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.totp_secret.as_deref(), Some(secret.expose_secret()));
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_page = app.get_two_factor_html().await;
+
+    // Act
+    let response = app.post_two_factor(&json!({ "code": "000000" })).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/password/two-factor");
+    let second_page = app.get_two_factor_html().await;
+    assert!(second_page.contains("<p><i>The code is incorrect.</i></p>"));
+    // The same secret is offered again, so the user does not have to rescan it.
+    let secret_of = |page: &str| page.split("Secret: <code>").nth(1).unwrap()[..32].to_owned();
+    assert_eq!(secret_of(&first_page), secret_of(&second_page));
+
+    let user = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(user.totp_secret.is_none());
+}
+
+#[tokio::test]
+async fn the_password_alone_does_not_log_in_an_enrolled_user() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    enroll_test_user(&app).await;
+
+    // Act
+    let response = log_in_with_password(&app).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_valid_code_completes_the_login() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    log_in_with_password(&app).await;
+
+    // Act
+    let response = app
+        .post_login_two_factor(&json!({ "code": current_code(&secret) }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_code_is_rejected() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    enroll_test_user(&app).await;
+    log_in_with_password(&app).await;
+
+    // Act
+    let response = app
+        .post_login_two_factor(&json!({ "code": "000000" }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    let code = current_code(&secret);
+    log_in_with_password(&app).await;
+    app.post_login_two_factor(&json!({ "code": code })).await;
+    app.post_logout().await;
+
+    // Act
+    log_in_with_password(&app).await;
+    let response = app.post_login_two_factor(&json!({ "code": code })).await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let (_, recovery_codes) = enroll_test_user(&app).await;
+
+    // Act - Part 1 - Use a recovery code
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&json!({ "code": recovery_codes[0].to_uppercase() }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - Use it again
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&json!({ "code": recovery_codes[0] }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_step_requires_the_password_first() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    // Act
+    let response = app
+        .post_login_two_factor(&json!({ "code": current_code(&secret) }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn disabling_two_factor_requires_the_current_password() {
+    // Arrange
+    let app = TestApp::spawn_app().await;
+    app.test_user.login(&app).await;
+    enroll_test_user(&app).await;
+
+    // Act - Part 1 - Wrong password
+    let response = app
+        .post_disable_two_factor(&json!({ "current_password": "wrong-password" }))
+        .await;
+    TestApp::assert_is_redirect_to(&response, "/admin/password/two-factor");
+    assert!(app
+        .get_two_factor_html()
+        .await
+        .contains("<p><i>The current password is incorrect.</i></p>"));
+
+    // Act - Part 2 - Right password
+    app.post_disable_two_factor(&json!({ "current_password": &app.test_user.password }))
+        .await;
+
+    // Assert
+    let user = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(user.totp_secret.is_none());
+}
+
+#[tokio::test]
+async fn wrong_codes_lock_the_username_out_even_after_the_right_password() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.login_lockout.max_consecutive_failures = 3;
+        config.login_lockout.lockout_seconds = 900;
+    })
+    .await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    // Act - Part 1 - Enter the right password before every wrong code
+    for _ in 0..3 {
+        let response = log_in_with_password(&app).await;
+        TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+        let response = app
+            .post_login_two_factor(&json!({ "code": "000000" }))
+            .await;
+        TestApp::assert_is_redirect_to(&response, "/login/two-factor");
+    }
+
+    // Act - Part 2 - The right code no longer helps
+    let response = app
+        .post_login_two_factor(&json!({ "code": current_code(&secret) }))
+        .await;
+
+    // Assert
+    TestApp::assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts"));
+    let response = app.get_admin_dashboard().await;
+    TestApp::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn codes_are_rate_limited_per_user_across_clients() {
+    // Arrange
+    let app = TestApp::spawn_app_with(|config| {
+        config.rate_limit.login.per_ip = None;
+        // The password step has the same quota, and both clients go through it.
+        config.rate_limit.login.per_identity = Some(Quota {
+            max_requests: 2,
+            window_seconds: 3600,
+        });
+    })
+    .await;
+    enroll_test_user(&app).await;
+    let other_client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    log_in_with_password(&app).await;
+    other_client
+        .post(format!("{}/login", &app.address))
+        .form(&json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let first = app
+        .post_login_two_factor(&json!({ "code": "000000" }))
+        .await;
+    let second = app
+        .post_login_two_factor(&json!({ "code": "000000" }))
+        .await;
+    let third = other_client
+        .post(format!("{}/login/two-factor", &app.address))
+        .form(&json!({ "code": "000000" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(303, first.status().as_u16());
+    assert_eq!(303, second.status().as_u16());
+    assert_eq!(429, third.status().as_u16());
+}